@@ -1,20 +1,35 @@
+use crate::clipboard;
+use crate::generator;
+use crate::history::History;
+use crate::item::{Action, Attachment, Field, Item, ItemAction, NewLogin};
 use crate::provider::{NewProvider, Provider};
 use crate::providers::bitwarden::Bitwarden;
 use crate::providers::keyhub::Keyhub;
 use crate::providers::password_store::PasswordStore;
 use crate::providers::terraform::Terraform;
-use crate::rofi::{RofiResponse, RofiWindow};
-use anyhow::Result;
+use crate::report::{self, Finding};
+use crate::rofi::{self, ask, choose, escape_markup, RofiResponse, RofiWindow};
+use crate::totp::{self, Totp};
+use crate::window::ActiveWindow;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
 use serde::Deserialize;
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::fs;
+use std::env;
+use std::fs::{self, DirBuilder};
 use std::io;
-use std::path::PathBuf;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use xdg;
 
-#[serde(rename_all = "camelCase")]
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct ProviderConfig {
     #[serde(rename = "type")]
     type_: String,
@@ -23,10 +38,29 @@ struct ProviderConfig {
 }
 
 // TODO: shared rofi args
-#[serde(rename_all = "camelCase")]
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct Config {
     providers: HashMap<String, ProviderConfig>,
+    /// Pre-fill the filter with a hint from the focused window.
+    #[serde(default)]
+    prefilter: bool,
+    #[serde(default)]
+    order: Order,
+    /// Browse the folders one level at a time instead of listing all items.
+    #[serde(default)]
+    tree: bool,
+    #[serde(default)]
+    report: report::Config,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Order {
+    /// Favorites first, then by frecency.
+    #[default]
+    Ranked,
+    Alphabetical,
 }
 
 pub struct App {
@@ -56,9 +90,8 @@ impl App {
         Ok(App { config, xdg_dirs })
     }
 
-    pub fn show(&self) -> Result<()> {
-        let providers: HashMap<String, RefCell<Box<dyn Provider>>> = self
-            .config
+    fn open_providers(&self) -> HashMap<String, RefCell<Box<dyn Provider>>> {
+        self.config
             .providers
             .iter()
             .map(|(key, provider)| match PROVIDERS.get(&provider.type_[..]) {
@@ -68,12 +101,29 @@ impl App {
                 ),
                 None => panic!("Provider {} does not exist", key),
             })
-            .collect();
+            .collect()
+    }
 
-        // TODO: deterministic sort order
-        let (key, provider) = providers.iter().next().unwrap();
+    /// The health report of one provider, without the main menu.
+    pub fn report(&self) -> Result<()> {
+        let providers = self.open_providers();
+        if let Some(key) = choose_provider(&providers)? {
+            if let Err(err) = show_report(&mut **providers[&key].borrow_mut(), &self.config.report)
+            {
+                rofi::show_error(&format!("{}: {}", key, err))?;
+            }
+        }
+        Ok(())
+    }
 
-        eprintln!("First provider = {}", key);
+    pub fn show(&self) -> Result<()> {
+        // Before rofi takes the focus.
+        let window = ActiveWindow::get();
+
+        let providers = self.open_providers();
+
+        let mut keys: Vec<&String> = providers.keys().collect();
+        keys.sort();
 
         // TODO: provider needs to know:
         // - the shortcuts to the rest
@@ -91,38 +141,807 @@ impl App {
         // - sync (and update cache)
         // - lock
 
-        let mut entries: Vec<String> = vec![];
-        for i in provider.borrow_mut().list_items()?.iter() {
-            entries.push(i.title.clone());
+        let mut history = History::try_load(&self.xdg_dirs.place_data_file("history.json")?);
+        let mut order = self.config.order;
+
+        // Items of every provider, by the key of their provider. With more
+        // than one provider the titles start with that key.
+        let mut items: Vec<(String, Item)> = vec![];
+        let mut statuses: Vec<String> = vec![];
+        for key in keys.iter() {
+            let mut provider = providers[*key].borrow_mut();
+            match provider.list_items() {
+                Ok(list) => items.extend(list.into_iter().map(|mut i| {
+                    if keys.len() > 1 {
+                        i.title = format!("{}/{}", key, i.title);
+                    }
+                    (key.to_string(), i)
+                })),
+                Err(err) => {
+                    eprintln!("{}: listing items failed: {}", key, err);
+                    statuses.push(format!("{}: {}", key, escape_markup(&err.to_string())));
+                }
+            }
+            match provider.status() {
+                Ok(Some(status)) if keys.len() > 1 => {
+                    statuses.push(format!("{}: {}", key, escape_markup(&status)))
+                }
+                Ok(Some(status)) => statuses.push(escape_markup(&status)),
+                Ok(None) => {}
+                Err(err) => {
+                    eprintln!("{}: getting status failed: {}", key, err);
+                    statuses.push(format!("{}: {}", key, escape_markup(&err.to_string())));
+                }
+            }
+        }
+        let hint = window.as_ref().and_then(|w| w.hint());
+
+        let writable: Vec<&String> = keys
+            .iter()
+            .copied()
+            .filter(|key| providers[*key].borrow().is_writable())
+            .collect();
+
+        // Actions bound to custom keys from FIRST_ACTION_KEY on. When
+        // providers share a shortcut the first one gets it.
+        let mut actions: Vec<(String, Action)> = vec![];
+        for key in keys.iter() {
+            for action in providers[*key].borrow_mut().list_actions()? {
+                if actions.iter().any(|(_, a)| a.shortcut == action.shortcut) {
+                    eprintln!("{}: {} is already bound", key, action.shortcut);
+                } else {
+                    actions.push((key.to_string(), action));
+                }
+            }
+        }
+
+        // The folder being browsed in tree mode.
+        let mut path: Vec<String> = vec![];
+
+        loop {
+            match order {
+                Order::Alphabetical => items.sort_by(|(_, a), (_, b)| a.title.cmp(&b.title)),
+                Order::Ranked => items.sort_by(|(a_key, a), (b_key, b)| {
+                    let score = |key: &str, i: &Item| history.score(&History::key(key, &i.id));
+                    b.favorite
+                        .cmp(&a.favorite)
+                        .then_with(|| {
+                            score(b_key, b)
+                                .partial_cmp(&score(a_key, a))
+                                .unwrap_or(Ordering::Equal)
+                        })
+                        .then_with(|| a.title.cmp(&b.title))
+                }),
+            }
+
+            // Items for the focused window first.
+            if let Some(window) = &window {
+                let text = window.text();
+                items.sort_by_key(|(_, i)| !matches_window(i, &text, hint.as_deref()));
+            }
+
+            let (folders, visible) = if self.config.tree {
+                browse(&items, &path)
+            } else {
+                (vec![], items.iter().collect())
+            };
+
+            let mut entries: Vec<String> = vec![];
+            if !path.is_empty() {
+                entries.push("..".to_owned());
+            }
+            let folder_entries: Vec<String> = folders
+                .iter()
+                .map(|f| format!("{}/", escape_markup(f)))
+                .collect();
+            entries.extend(folder_entries.iter().cloned());
+            for (_, i) in visible.iter() {
+                entries.push(entry(i, &path));
+            }
+
+            let mut message = format!(
+                "<b>Alt+r</b>: sync all | <b>Alt+l</b>: lock all | <b>Alt+h</b>: health report | <b>Alt+o</b>: {}",
+                match order {
+                    Order::Ranked => "sort alphabetically",
+                    Order::Alphabetical => "sort by usage",
+                }
+            );
+            if !writable.is_empty() {
+                message = format!("{} | <b>Alt+n</b>: new item", message);
+            }
+            for (key, action) in actions.iter() {
+                message = match keys.len() {
+                    1 => format!("{} | <b>{}</b>: {}", message, action.shortcut, action.title),
+                    _ => format!(
+                        "{} | <b>{}</b>: {} ({})",
+                        message, action.shortcut, action.title, key
+                    ),
+                };
+            }
+            if !path.is_empty() {
+                message = format!("{} | <b>Alt+BackSpace</b>: back", message);
+            }
+            if !statuses.is_empty() {
+                message = format!("{}\n{}", statuses.join("\n"), message);
+            }
+
+            // TODO: let rofi return an integer of the index selected.
+            let mut rofi = RofiWindow::new("Select an entry")
+                .matching("fuzzy")
+                .kb_custom(1, "Alt+r")
+                .kb_custom(2, "Alt+l")
+                .kb_custom(3, "Alt+o")
+                .kb_custom(4, "Alt+BackSpace")
+                .kb_custom(5, "Alt+n")
+                .kb_custom(6, "Alt+h")
+                .message(&message)
+                .add_args(vec!["-dmenu", "-markup-rows"])
+                .lines(15);
+
+            for (i, (_, action)) in actions.iter().enumerate() {
+                rofi = rofi.kb_custom(FIRST_ACTION_KEY + i as i32, &action.shortcut);
+            }
+
+            // Only filter when that leaves something to choose from.
+            if let Some(hint) = hint.as_ref().filter(|_| self.config.prefilter) {
+                if items
+                    .iter()
+                    .any(|(_, i)| i.title.to_lowercase().contains(hint))
+                {
+                    rofi = rofi.filter(hint);
+                }
+            }
+
+            let res = rofi
+                .show(entries.clone())
+                .expect("Creating rofi window failed");
+
+            match res {
+                RofiResponse::Entry(ref s) if s == ".." => {
+                    path.pop();
+                    continue;
+                }
+                RofiResponse::Entry(ref s) if folder_entries.contains(s) => {
+                    let idx = folder_entries.iter().position(|f| f == s).unwrap();
+                    path.push(folders[idx].clone());
+                    continue;
+                }
+                RofiResponse::Entry(s) => {
+                    match visible.iter().find(|(_, i)| entry(i, &path) == s) {
+                        Some((key, item)) => {
+                            match show_item(&mut **providers[key].borrow_mut(), item) {
+                                Ok(()) => history.record(&History::key(key, &item.id)),
+                                Err(err) => rofi::show_error(&format!("{}: {}", item.title, err))?,
+                            }
+                        }
+                        None => eprintln!("No such entry: {}", s),
+                    }
+                }
+                RofiResponse::Cancel => println!("Bye."),
+                RofiResponse::CustomKey(1) => sync_all(&providers)?,
+                RofiResponse::CustomKey(2) => lock_all(&providers)?,
+                RofiResponse::CustomKey(3) => {
+                    order = match order {
+                        Order::Ranked => Order::Alphabetical,
+                        Order::Alphabetical => Order::Ranked,
+                    };
+                    continue;
+                }
+                RofiResponse::CustomKey(4) => {
+                    path.pop();
+                    continue;
+                }
+                RofiResponse::CustomKey(5) if !writable.is_empty() => {
+                    let key = match writable.len() {
+                        1 => Some(writable[0].to_owned()),
+                        _ => choose(
+                            "New item in",
+                            writable.iter().map(|k| k.to_string()).collect(),
+                        )?,
+                    };
+                    if let Some(key) = key.filter(|k| writable.contains(&k)) {
+                        if let Err(err) = create_item(&mut **providers[&key].borrow_mut()) {
+                            rofi::show_error(&format!("{}: {}", key, err))?;
+                        }
+                    }
+                }
+                RofiResponse::CustomKey(6) => {
+                    if let Some(key) = choose_provider(&providers)? {
+                        let mut provider = providers[&key].borrow_mut();
+                        if let Err(err) = show_report(&mut **provider, &self.config.report) {
+                            rofi::show_error(&format!("{}: {}", key, err))?;
+                        }
+                    }
+                }
+                RofiResponse::CustomKey(n)
+                    if n >= FIRST_ACTION_KEY
+                        && ((n - FIRST_ACTION_KEY) as usize) < actions.len() =>
+                {
+                    let (key, action) = &actions[(n - FIRST_ACTION_KEY) as usize];
+                    if let Err(err) = providers[key].borrow_mut().do_action(action) {
+                        rofi::show_error(&format!("{}: {}", action.title, err))?;
+                    }
+                }
+                RofiResponse::CustomKey(key) => println!("Custom key {}", key),
+            }
+
+            return Ok(());
         }
+    }
 
-        entries.sort();
+    pub fn get_cache_file(&self, name: &str) -> io::Result<PathBuf> {
+        self.xdg_dirs.place_cache_file(name)
+    }
+
+    pub fn get_data_file(&self, name: &str) -> io::Result<PathBuf> {
+        self.xdg_dirs.place_data_file(name)
+    }
+}
+
+/// The only provider, or the one the user chooses.
+fn choose_provider(
+    providers: &HashMap<String, RefCell<Box<dyn Provider>>>,
+) -> Result<Option<String>> {
+    let mut keys: Vec<String> = providers.keys().cloned().collect();
+    keys.sort();
+    match keys.len() {
+        1 => Ok(keys.pop()),
+        _ => Ok(choose("Provider", keys)?.filter(|k| providers.contains_key(k))),
+    }
+}
+
+/// Reused, weak and old passwords of a provider. Choosing one shows the
+/// item, after which the report is shown again.
+fn show_report(provider: &mut dyn Provider, config: &report::Config) -> Result<()> {
+    let passwords = provider.list_passwords()?;
+    let total = passwords.len();
+    let findings = report::check(passwords, config);
+
+    let count = |f: fn(&report::Problem) -> bool| {
+        findings
+            .iter()
+            .filter(|finding| finding.problems.iter().any(f))
+            .count()
+    };
+    let message = format!(
+        "{} passwords: {} reused, {} weak, {} older than {} days",
+        total,
+        count(|p| matches!(p, report::Problem::Reused(_))),
+        count(|p| matches!(p, report::Problem::Weak(_))),
+        count(|p| matches!(p, report::Problem::Old(_))),
+        config.max_age_days
+    );
 
-        // TODO: let rofi return an integer of the index selected.
-        let res = RofiWindow::new("Select an entry")
+    let rows: Vec<String> = findings.iter().map(report_row).collect();
+    loop {
+        let res = RofiWindow::new("Health report")
             .matching("fuzzy")
-            .kb_custom(1, "Alt+r")
-            .kb_custom(2, "Alt+l")
-            .message("<b>Alt+r</b>: sync | <b>Alt+l</b>: lock")
+            .message(&message)
             .add_args(vec!["-dmenu", "-markup-rows"])
-            .lines(15)
-            .show(entries.clone())
-            .expect("Creating rofi window failed");
+            .lines(rows.len().min(15) as i32)
+            .show(rows.clone())?;
+
+        let finding = match res {
+            RofiResponse::Entry(s) => match rows.iter().position(|r| *r == s) {
+                Some(n) => &findings[n],
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        if let Err(err) = show_item(provider, &finding.item) {
+            rofi::show_error(&format!("{}: {}", finding.item.title, err))?;
+        }
+    }
+}
+
+fn report_row(finding: &Finding) -> String {
+    let problems: Vec<String> = finding.problems.iter().map(|p| p.describe()).collect();
+    format!(
+        "{} <span alpha=\"50%\">{}</span>",
+        escape_markup(&finding.item.title),
+        problems.join(", ")
+    )
+}
+
+/// Menu row for an item, with its type as a tag. In tree mode the title is
+/// relative to the folder being browsed.
+fn entry(item: &Item, path: &[String]) -> String {
+    let title = item
+        .title
+        .splitn(path.len() + 1, '/')
+        .last()
+        .unwrap_or_default();
+    match item.kind.tag() {
+        Some(tag) => format!(
+            "{} <span alpha=\"50%\">[{}]</span>",
+            escape_markup(title),
+            tag
+        ),
+        None => escape_markup(title),
+    }
+}
+
+/// The sub folders and the items directly in a folder, with the item titles
+/// as paths.
+fn browse<'a>(
+    items: &'a [(String, Item)],
+    path: &[String],
+) -> (Vec<String>, Vec<&'a (String, Item)>) {
+    let mut folders: Vec<String> = vec![];
+    let mut visible = vec![];
+
+    for entry in items.iter() {
+        let i = &entry.1;
+        let segments: Vec<&str> = i.title.split('/').collect();
+        if segments.len() <= path.len() || segments.iter().zip(path).any(|(s, p)| s != p) {
+            continue;
+        }
 
-        match res {
-            RofiResponse::Entry(s) => {
-                println!("Entry = {}", s);
-                let idx = entries.iter().position(|x| x == &s);
-                println!("You chose entry: {:?}", idx);
+        if segments.len() == path.len() + 1 {
+            visible.push(entry);
+        } else if !folders.iter().any(|f| f == segments[path.len()]) {
+            folders.push(segments[path.len()].to_owned());
+        }
+    }
+
+    folders.sort();
+    (folders, visible)
+}
+
+/// Whether an item belongs to the focused window, by its URIs or by the
+/// window's hint in its title.
+fn matches_window(item: &Item, text: &str, hint: Option<&str>) -> bool {
+    item.uris.iter().any(|u| u.matches(text))
+        || hint.is_some_and(|hint| item.title.to_lowercase().contains(hint))
+}
+
+/// The first custom key used for the actions of providers, the ones before
+/// are bound by the main menu itself.
+const FIRST_ACTION_KEY: i32 = 7;
+
+/// Wait for the next TOTP code when the current one expires sooner.
+const TOTP_MIN_REMAINING: u64 = 5;
+
+fn show_item(provider: &mut dyn Provider, item: &Item) -> Result<()> {
+    let fields = provider.list_fields(item)?;
+    let mut names: Vec<String> = fields.iter().map(|f| f.name().to_owned()).collect();
+    for uri in item.uris.iter().filter(|u| u.is_web()) {
+        names.push(format!("open {}", uri.uri));
+    }
+    if !item.attachments.is_empty() {
+        names.push("attachments".to_owned());
+    }
+    if provider.has_password_history() && fields.contains(&Field::Password) {
+        names.push("password history".to_owned());
+    }
+    if provider.is_writable() {
+        names.push("edit".to_owned());
+    }
+    let actions = provider.item_actions();
+    for action in actions.iter() {
+        names.push(action.title(item).to_owned());
+    }
+
+    let choice = match names.len() {
+        0 => {
+            eprintln!("{} has no fields", item.title);
+            return Ok(());
+        }
+        1 => names[0].clone(),
+        _ => {
+            let res = RofiWindow::new(&item.title)
+                .add_args(vec!["-dmenu"])
+                .lines(names.len() as i32)
+                .show(names.clone())?;
+            match res {
+                RofiResponse::Entry(s) => s,
+                _ => return Ok(()),
             }
-            RofiResponse::Cancel => println!("Bye."),
-            RofiResponse::CustomKey(key) => println!("Custom key {}", key),
         }
+    };
 
-        Ok(())
+    let field = match fields.iter().find(|f| f.name() == choice) {
+        Some(field) => field.clone(),
+        None if choice == "attachments" => return show_attachments(provider, item),
+        None if choice == "password history" => return show_password_history(provider, item),
+        None if choice == "edit" => return edit_item(provider, item, &fields),
+        None => match actions.iter().find(|a| a.title(item) == choice) {
+            Some(action) => return do_item_action(provider, item, *action),
+            None => match choice.strip_prefix("open ") {
+                Some(url) => return open_url(url),
+                None => return Ok(()),
+            },
+        },
+    };
+
+    let value = provider.read_field(item, &field)?;
+    match field {
+        Field::Totp => show_totp(provider, item, &value),
+        Field::Notes => show_notes(item, &value),
+        _ => {
+            clipboard::copy(&value)?;
+            eprintln!("Copied {}", field.name());
+            Ok(())
+        }
     }
+}
 
-    pub fn get_cache_file(&self, name: &str) -> io::Result<PathBuf> {
-        self.xdg_dirs.place_cache_file(name)
+/// Ask for a new password, which is generated when left empty.
+fn ask_password(provider: &mut dyn Provider) -> Result<Option<String>> {
+    match ask("Password", Some("Leave empty to generate one"), true)? {
+        Some(password) if password.is_empty() => match generator::ask()? {
+            Some(options) => Ok(Some(provider.generate_password(&options)?)),
+            None => Ok(None),
+        },
+        password => Ok(password),
+    }
+}
+
+/// Row for items without a folder in the new item form.
+const NO_FOLDER: &str = "(no folder)";
+
+/// The form for a new login. The password is copied once it is created.
+fn create_item(provider: &mut dyn Provider) -> Result<()> {
+    let name = match ask("Name", None, false)? {
+        Some(name) if !name.is_empty() => name,
+        _ => return Ok(()),
+    };
+
+    let mut folders = vec![NO_FOLDER.to_owned()];
+    folders.extend(provider.list_folders()?);
+    let folder = match choose("Folder", folders)? {
+        Some(folder) if folder.is_empty() || folder == NO_FOLDER => None,
+        Some(folder) => Some(folder.trim_matches('/').to_owned()),
+        None => return Ok(()),
+    };
+
+    let username = match ask("Username", Some("Leave empty for none"), false)? {
+        Some(username) => Some(username).filter(|u| !u.is_empty()),
+        None => return Ok(()),
+    };
+    let password = match ask_password(provider)? {
+        Some(password) => password,
+        None => return Ok(()),
+    };
+    let uri = match ask("URL", Some("Leave empty for none"), false)? {
+        Some(uri) => Some(uri).filter(|u| !u.is_empty()),
+        None => return Ok(()),
+    };
+
+    let item = provider.create_item(&NewLogin {
+        name,
+        folder,
+        username,
+        password: password.clone(),
+        uri,
+    })?;
+    eprintln!("Created {}", item.title);
+
+    clipboard::copy(&password)
+}
+
+fn edit_item(provider: &mut dyn Provider, item: &Item, fields: &[Field]) -> Result<()> {
+    let names: Vec<String> = fields.iter().map(|f| f.name().to_owned()).collect();
+    let field = match choose(&format!("Edit {}", item.title), names)? {
+        Some(name) => match fields.iter().find(|f| f.name() == name) {
+            Some(field) => field.clone(),
+            None => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+
+    let value = match field {
+        Field::Password => ask_password(provider)?,
+        _ => ask(field.name(), None, field.is_secret())?,
+    };
+    match value {
+        Some(value) => {
+            provider.update_field(item, &field, &value)?;
+            eprintln!("Updated {} of {}", field.name(), item.title);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+fn do_item_action(provider: &mut dyn Provider, item: &Item, action: ItemAction) -> Result<()> {
+    match action {
+        ItemAction::Move => {
+            let mut folders = vec![NO_FOLDER.to_owned()];
+            folders.extend(provider.list_folders()?);
+            match choose(&format!("Move {} to", item.title), folders)? {
+                Some(folder) if folder.is_empty() || folder == NO_FOLDER => {
+                    provider.move_item(item, None)?
+                }
+                Some(folder) => provider.move_item(item, Some(folder.trim_matches('/')))?,
+                None => return Ok(()),
+            }
+            eprintln!("Moved {}", item.title);
+        }
+        ItemAction::Delete => {
+            let res = RofiWindow::new(&format!("Delete {}?", item.title))
+                .add_args(vec!["-dmenu"])
+                .lines(2)
+                .show(vec!["No".to_owned(), "Yes".to_owned()])?;
+            if let RofiResponse::Entry(ref s) = res {
+                if s == "Yes" {
+                    provider.delete_item(item)?;
+                    eprintln!("Deleted {}", item.title);
+                }
+            }
+        }
+        ItemAction::ToggleFavorite => {
+            provider.set_favorite(item, !item.favorite)?;
+            eprintln!("Toggled favorite of {}", item.title);
+        }
+    }
+    Ok(())
+}
+
+/// A file that is removed when dropped, for decrypted attachments.
+struct TempFile {
+    path: PathBuf,
+    dir: PathBuf,
+}
+
+impl TempFile {
+    /// A file in a new directory only the user can read, in XDG_RUNTIME_DIR
+    /// (a tmpfs) when possible.
+    fn new(name: &str) -> Result<TempFile> {
+        let parent = match env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from("/dev/shm"),
+        };
+        let dir = private_dir(&parent)?;
+
+        // No directories from the name.
+        let name = Path::new(name)
+            .file_name()
+            .map_or_else(|| "attachment".into(), |n| n.to_owned());
+        Ok(TempFile {
+            path: dir.join(name),
+            dir,
+        })
+    }
+}
+
+/// Create a directory with a random name in `parent`, like mkdtemp. Fails
+/// unless it ends up owned by the user and closed to everyone else.
+fn private_dir(parent: &Path) -> Result<PathBuf> {
+    let mut attempts = 0;
+    let dir = loop {
+        let suffix: String = OsRng
+            .sample_iter(&Alphanumeric)
+            .take(12)
+            .map(char::from)
+            .collect();
+        let dir = parent.join(format!("bitwarden_rofi.{}", suffix));
+        // Not recursive, so an existing directory is never used.
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => break dir,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempts < 10 => {
+                attempts += 1
+            }
+            Err(err) => return Err(err.into()),
+        }
+    };
+
+    let metadata = fs::symlink_metadata(&dir)?;
+    // Safe, geteuid cannot fail.
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        return Err(anyhow!("{} is not a private directory", dir.display()));
+    }
+    Ok(dir)
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let removed = [
+            (&self.path, fs::remove_file(&self.path)),
+            (&self.dir, fs::remove_dir(&self.dir)),
+        ];
+        for (path, result) in removed.iter() {
+            match result {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    eprintln!("Removing {} failed: {}", path.display(), err)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn show_attachments(provider: &mut dyn Provider, item: &Item) -> Result<()> {
+    let row = |a: &Attachment| match &a.size {
+        Some(size) => format!("{} ({})", a.file_name, size),
+        None => a.file_name.clone(),
+    };
+    let rows: Vec<String> = item.attachments.iter().map(row).collect();
+
+    let attachment = match choose(&format!("{} attachments", item.title), rows)? {
+        Some(s) => match item.attachments.iter().find(|a| row(a) == s) {
+            Some(attachment) => attachment,
+            None => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+    let copy = match choose(
+        &attachment.file_name,
+        vec!["open".to_owned(), "copy contents".to_owned()],
+    )? {
+        Some(s) if s == "open" => false,
+        Some(s) if s == "copy contents" => true,
+        _ => return Ok(()),
+    };
+
+    let file = TempFile::new(&attachment.file_name)?;
+    provider.save_attachment(item, attachment, &file.path)?;
+
+    if copy {
+        let contents = fs::read_to_string(&file.path)?;
+        clipboard::copy(&contents)?;
+        eprintln!("Copied {}", attachment.file_name);
+        return Ok(());
+    }
+
+    open_url(&file.path.to_string_lossy())?;
+    // xdg-open returns before the application has read the file.
+    RofiWindow::new(&attachment.file_name)
+        .message("Opened, close this to remove the file")
+        .add_args(vec!["-dmenu"])
+        .lines(1)
+        .show(vec!["Remove".to_owned()])?;
+    Ok(())
+}
+
+/// The previous passwords by the date they were replaced, to copy one.
+fn show_password_history(provider: &mut dyn Provider, item: &Item) -> Result<()> {
+    let history = provider.password_history(item)?;
+    let format = |date: &DateTime<Utc>| {
+        date.with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+
+    let mut message = match &history.revision_date {
+        Some(date) => format!("Current password set {}", format(date)),
+        None => "Current password was never changed".to_owned(),
+    };
+    if history.passwords.is_empty() {
+        message = format!("{}\nNo previous passwords", message);
+    }
+
+    let rows: Vec<String> = history
+        .passwords
+        .iter()
+        .enumerate()
+        .map(|(n, old)| format!("{}. used until {}", n + 1, format(&old.last_used)))
+        .collect();
+
+    let res = RofiWindow::new(&format!("{} password history", item.title))
+        .message(&message)
+        .add_args(vec!["-dmenu"])
+        .lines(rows.len().min(15) as i32)
+        .show(rows.clone())?;
+
+    if let RofiResponse::Entry(s) = res {
+        if let Some(n) = rows.iter().position(|r| *r == s) {
+            clipboard::copy(&history.passwords[n].password)?;
+            eprintln!("Copied previous password {}", n + 1);
+        }
+    }
+    Ok(())
+}
+
+fn show_totp(provider: &mut dyn Provider, item: &Item, secret: &str) -> Result<()> {
+    let totp = Totp::parse(secret)?;
+    // An HOTP code is used up once shown, so store the next counter first.
+    if let Some(counter) = totp.counter() {
+        if !provider.is_writable() {
+            return Err(anyhow!(
+                "Cannot store the next HOTP counter of {}",
+                item.title
+            ));
+        }
+        let next = totp::set_counter(secret, counter + 1)?;
+        provider.update_field(item, &Field::Totp, &next)?;
+    }
+    let (code, remaining) = totp.fresh(TOTP_MIN_REMAINING);
+
+    let mut window = RofiWindow::new(&item.title)
+        .add_args(vec!["-dmenu"])
+        .lines(1);
+    if let Some(remaining) = remaining {
+        window = window.message(&format!("Valid for {} seconds", remaining));
+    }
+
+    if let RofiResponse::Entry(code) = window.show(vec![code])? {
+        clipboard::copy(&code)?;
+    }
+
+    Ok(())
+}
+
+/// Show the notes line by line, to copy either a single line or all of them.
+fn show_notes(item: &Item, notes: &str) -> Result<()> {
+    let lines: Vec<String> = notes
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.to_owned())
+        .collect();
+
+    let res = RofiWindow::new(&item.title)
+        .kb_custom(1, "Alt+a")
+        .message("<b>Enter</b>: copy line | <b>Alt+a</b>: copy all")
+        .add_args(vec!["-dmenu"])
+        .lines(lines.len().min(15) as i32)
+        .show(lines)?;
+
+    match res {
+        RofiResponse::Entry(line) => clipboard::copy(&line),
+        RofiResponse::CustomKey(1) => clipboard::copy(notes),
+        _ => Ok(()),
+    }
+}
+
+fn open_url(url: &str) -> Result<()> {
+    let status = Command::new("xdg-open").arg(url).status()?;
+    if !status.success() {
+        eprintln!("xdg-open {} exited with {}", url, status);
+    }
+    Ok(())
+}
+
+fn sync_all(providers: &HashMap<String, RefCell<Box<dyn Provider>>>) -> Result<()> {
+    let mut errors = vec![];
+    for (key, provider) in providers.iter() {
+        match provider.borrow_mut().sync() {
+            Ok(()) => eprintln!("{}: synced", key),
+            Err(err) => errors.push(format!("{}: sync failed: {}", key, err)),
+        }
+    }
+    show_errors(&errors)
+}
+
+fn lock_all(providers: &HashMap<String, RefCell<Box<dyn Provider>>>) -> Result<()> {
+    let mut errors = vec![];
+    for (key, provider) in providers.iter() {
+        match provider.borrow_mut().lock() {
+            Ok(()) => eprintln!("{}: locked", key),
+            Err(err) => errors.push(format!("{}: lock failed: {}", key, err)),
+        }
+    }
+    show_errors(&errors)
+}
+
+/// Show the errors of all providers at once, after trying every one.
+fn show_errors(errors: &[String]) -> Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    eprintln!("{}", errors.join("\n"));
+    rofi::show_error(&errors.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn private_dirs_are_new_and_closed() {
+        let parent = env::temp_dir();
+        let first = private_dir(&parent).unwrap();
+        let second = private_dir(&parent).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(parent.as_path()));
+
+        let mode = fs::metadata(&first).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        fs::remove_dir(first).unwrap();
+        fs::remove_dir(second).unwrap();
+        assert!(private_dir(Path::new("/nonexistent/dir")).is_err());
     }
 }
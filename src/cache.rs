@@ -1,4 +1,6 @@
 use crate::item::Item;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::fs::File;
 use std::io::Write;
@@ -6,34 +8,65 @@ use std::path::{Path, PathBuf};
 
 pub struct Cache {
     path: PathBuf,
+    contents: Contents,
+}
+
+/// The cache file.
+#[derive(Serialize, Deserialize, Default)]
+struct Contents {
+    /// When the items were listed. Editing an item keeps this as it is, the
+    /// other items are still as old.
+    listed: Option<DateTime<Utc>>,
     items: Vec<Item>,
+    /// The account the items are of, to show without opening the session.
+    #[serde(default)]
+    status: Option<String>,
 }
 
 impl Cache {
     pub fn try_load(path: &Path) -> Cache {
-        let items = match fs::read_to_string(path) {
+        let contents = match fs::read_to_string(path) {
             Ok(contents) => match serde_json::from_str(&contents) {
-                Ok(items) => items,
-                Err(err) => {
-                    eprintln!("Could not deserialize cache: {}", err);
-                    vec![]
-                }
+                Ok(contents) => contents,
+                // Older caches only have the items, of unknown age.
+                Err(err) => match serde_json::from_str(&contents) {
+                    Ok(items) => Contents {
+                        items,
+                        ..Contents::default()
+                    },
+                    Err(_) => {
+                        eprintln!("Could not deserialize cache: {}", err);
+                        Contents::default()
+                    }
+                },
             },
             Err(err) => {
                 eprintln!("Could not read cache: {}", err);
-                vec![]
+                Contents::default()
             }
         };
 
         Cache {
-            items,
             path: path.to_owned(),
+            contents,
         }
     }
 
     pub fn replace(&mut self, items: Vec<Item>) {
-        self.items = items;
+        self.contents.items = items;
+        self.contents.listed = Some(Utc::now());
+        self.save();
+    }
 
+    /// Replace the items with an id by their new versions, none when the
+    /// item was deleted.
+    pub fn update(&mut self, id: &str, items: Vec<Item>) {
+        self.contents.items.retain(|i| i.id != id);
+        self.contents.items.extend(items);
+        self.save();
+    }
+
+    fn save(&self) {
         let mut file = match File::create(&self.path) {
             Ok(file) => file,
             Err(err) => {
@@ -42,7 +75,7 @@ impl Cache {
             }
         };
 
-        let contents = serde_json::to_string(&self.items).unwrap();
+        let contents = serde_json::to_string(&self.contents).unwrap();
         match file.write_all(contents.as_bytes()) {
             Err(err) => eprintln!("Writing cache file failed: {}", err),
             Ok(_) => eprintln!("Cache updated"),
@@ -50,6 +83,88 @@ impl Cache {
     }
 
     pub fn items(&self) -> &Vec<Item> {
-        &self.items
+        &self.contents.items
+    }
+
+    pub fn listed(&self) -> Option<DateTime<Utc>> {
+        self.contents.listed
+    }
+
+    pub fn status(&self) -> Option<&str> {
+        self.contents.status.as_deref()
+    }
+
+    pub fn set_status(&mut self, status: &str) {
+        if self.status() != Some(status) {
+            self.contents.status = Some(status.to_owned());
+            self.save();
+        }
+    }
+
+    /// Whether there are items listed less than `max_age` ago.
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        !self.contents.items.is_empty()
+            && self
+                .contents
+                .listed
+                .is_some_and(|listed| Utc::now() - listed < max_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::Kind;
+
+    fn item(id: &str) -> Item {
+        Item {
+            id: id.to_owned(),
+            title: id.to_owned(),
+            fields: vec![],
+            kind: Kind::Login,
+            uris: vec![],
+            favorite: false,
+            attachments: vec![],
+        }
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "bitwarden_rofi_test_{}_{}.json",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn updates_keep_the_listing_time() {
+        let path = path("updates_keep_the_listing_time");
+        let mut cache = Cache::try_load(&path);
+        assert!(!cache.is_fresh(Duration::hours(1)));
+
+        cache.replace(vec![item("1"), item("2")]);
+        let listed = Utc::now() - Duration::hours(2);
+        cache.contents.listed = Some(listed);
+        cache.save();
+
+        let mut cache = Cache::try_load(&path);
+        cache.update("1", vec![]);
+        let cache = Cache::try_load(&path);
+        assert_eq!(cache.items().len(), 1);
+        assert_eq!(cache.contents.listed, Some(listed));
+        assert!(!cache.is_fresh(Duration::hours(1)));
+        assert!(cache.is_fresh(Duration::hours(3)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn old_caches_are_stale() {
+        let path = path("old_caches_are_stale");
+        fs::write(&path, serde_json::to_string(&vec![item("1")]).unwrap()).unwrap();
+
+        let cache = Cache::try_load(&path);
+        assert_eq!(cache.items().len(), 1);
+        assert!(!cache.is_fresh(Duration::hours(1)));
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,56 @@
+use anyhow::{anyhow, Result};
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Put text on the clipboard using xclip.
+pub fn copy(text: &str) -> Result<()> {
+    let mut p = Command::new("xclip")
+        .args(["-selection", "clipboard"])
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    {
+        let stdin = p.stdin.as_mut().unwrap();
+        stdin.write_all(text.as_bytes())?;
+    }
+
+    let status = p.wait()?;
+    if !status.success() {
+        return Err(anyhow!("xclip exited with {}", status));
+    }
+
+    Ok(())
+}
+
+/// Text on the clipboard.
+pub fn paste() -> Result<String> {
+    let output = Command::new("xclip")
+        .args(["-selection", "clipboard", "-o"])
+        .stdin(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow!("xclip exited with {}", output.status));
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Type text into the focused window, using wtype on Wayland and xdotool
+/// on X11.
+pub fn type_text(text: &str) -> Result<()> {
+    let status = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        Command::new("wtype").args(["--", text]).status()?
+    } else {
+        Command::new("xdotool")
+            .args(["type", "--clearmodifiers", "--", text])
+            .status()?
+    };
+
+    if !status.success() {
+        return Err(anyhow!("Typing exited with {}", status));
+    }
+
+    Ok(())
+}
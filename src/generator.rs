@@ -0,0 +1,204 @@
+//! Password and passphrase generation, for providers that cannot generate
+//! them themselves.
+
+use crate::clipboard;
+use crate::rofi::{RofiResponse, RofiWindow};
+use anyhow::{anyhow, Result};
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use std::fs;
+use std::path::Path;
+
+/// Title of the action providers offer to generate a password.
+pub const ACTION: &str = "Generate password";
+
+/// Used for passphrases when no wordlist is configured.
+const DEFAULT_WORDLIST: &str = "/usr/share/dict/words";
+
+const UPPERCASE: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
+const LOWERCASE: &str = "abcdefghijkmnopqrstuvwxyz";
+const NUMBERS: &str = "23456789";
+const SPECIAL: &str = "!@#$%^&*";
+
+#[derive(Debug, Clone)]
+pub enum Options {
+    Password {
+        length: usize,
+        uppercase: bool,
+        lowercase: bool,
+        numbers: bool,
+        special: bool,
+    },
+    Passphrase {
+        words: usize,
+        separator: String,
+    },
+}
+
+/// Character classes to choose from, as shown in the menu.
+const CLASSES: &[(&str, bool, bool, bool, bool)] = &[
+    ("A-Z a-z 0-9 !@#", true, true, true, true),
+    ("A-Z a-z 0-9", true, true, true, false),
+    ("a-z 0-9", false, true, true, false),
+    ("0-9", false, false, true, false),
+];
+
+/// Ask for the kind of password and its options. Gives `None` when cancelled.
+pub fn ask() -> Result<Option<Options>> {
+    let kinds = vec!["Password".to_owned(), "Passphrase".to_owned()];
+    let kind = match RofiWindow::new(ACTION)
+        .add_args(vec!["-dmenu"])
+        .lines(kinds.len() as i32)
+        .show(kinds)?
+    {
+        RofiResponse::Entry(kind) => kind,
+        _ => return Ok(None),
+    };
+
+    if kind == "Passphrase" {
+        let words = match ask_number("Number of words", 5)? {
+            Some(words) => words,
+            None => return Ok(None),
+        };
+        let separator = match ask_text("Separator", "-")? {
+            Some(separator) => separator,
+            None => return Ok(None),
+        };
+        return Ok(Some(Options::Passphrase { words, separator }));
+    }
+
+    let length = match ask_number("Length", 20)? {
+        Some(length) => length,
+        None => return Ok(None),
+    };
+    let names: Vec<String> = CLASSES.iter().map(|c| c.0.to_owned()).collect();
+    let class = match RofiWindow::new("Characters")
+        .add_args(vec!["-dmenu"])
+        .lines(names.len() as i32)
+        .show(names)?
+    {
+        RofiResponse::Entry(name) => CLASSES.iter().find(|c| c.0 == name),
+        _ => return Ok(None),
+    };
+    let (_, uppercase, lowercase, numbers, special) =
+        class.ok_or_else(|| anyhow!("Unknown character classes"))?;
+
+    Ok(Some(Options::Password {
+        length,
+        uppercase: *uppercase,
+        lowercase: *lowercase,
+        numbers: *numbers,
+        special: *special,
+    }))
+}
+
+fn ask_text(prompt: &str, default: &str) -> Result<Option<String>> {
+    let res = RofiWindow::new(prompt)
+        .message(&format!("Leave empty for \"{}\"", default))
+        .add_args(vec!["-dmenu"])
+        .lines(0)
+        .show(vec![])?;
+    Ok(match res {
+        RofiResponse::Entry(s) if s.is_empty() => Some(default.to_owned()),
+        RofiResponse::Entry(s) => Some(s),
+        _ => None,
+    })
+}
+
+fn ask_number(prompt: &str, default: usize) -> Result<Option<usize>> {
+    match ask_text(prompt, &default.to_string())? {
+        Some(s) => match s.trim().parse() {
+            Ok(0) | Err(_) => Err(anyhow!("{} must be a positive number", prompt)),
+            Ok(n) => Ok(Some(n)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Generate a password with the built-in generator. Passphrases use words
+/// from the wordlist, one per line, eg. the EFF large wordlist.
+pub fn generate(options: &Options, wordlist: Option<&Path>) -> Result<String> {
+    let mut rng = OsRng;
+
+    match options {
+        Options::Password {
+            length,
+            uppercase,
+            lowercase,
+            numbers,
+            special,
+        } => {
+            let classes: Vec<&[u8]> = [
+                (*uppercase, UPPERCASE),
+                (*lowercase, LOWERCASE),
+                (*numbers, NUMBERS),
+                (*special, SPECIAL),
+            ]
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, chars)| chars.as_bytes())
+            .collect();
+            if classes.is_empty() {
+                return Err(anyhow!("No characters to generate a password from"));
+            }
+            let chars: Vec<u8> = classes.concat();
+
+            // Retry until every class is used, which for sensible lengths
+            // takes only a few tries.
+            loop {
+                let password: Vec<u8> = (0..*length)
+                    .map(|_| *chars.choose(&mut rng).unwrap())
+                    .collect();
+                let complete = *length < classes.len()
+                    || classes
+                        .iter()
+                        .all(|class| password.iter().any(|c| class.contains(c)));
+                if complete {
+                    return Ok(String::from_utf8(password)?);
+                }
+            }
+        }
+        Options::Passphrase { words, separator } => {
+            let path = wordlist.unwrap_or_else(|| Path::new(DEFAULT_WORDLIST));
+            let contents = fs::read_to_string(path)
+                .map_err(|err| anyhow!("Reading wordlist {} failed: {}", path.display(), err))?;
+            let list = wordlist_words(&contents);
+            if list.is_empty() {
+                return Err(anyhow!("No words in wordlist {}", path.display()));
+            }
+
+            let chosen: Vec<&str> = (0..*words)
+                .map(|_| *list.choose(&mut rng).unwrap())
+                .collect();
+            Ok(chosen.join(separator))
+        }
+    }
+}
+
+/// The words of a wordlist. Diceware lists have the dice rolls in front of
+/// every word, those are skipped.
+fn wordlist_words(contents: &str) -> Vec<&str> {
+    let mut words: Vec<&str> = contents
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .filter(|word| word.chars().all(|c| c.is_ascii_lowercase() || c == '-'))
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    words
+}
+
+/// Let the user choose between copying and typing a generated password.
+pub fn deliver(password: &str) -> Result<()> {
+    let choices = vec!["Copy".to_owned(), "Type".to_owned()];
+    let res = RofiWindow::new("Generated password")
+        .add_args(vec!["-dmenu"])
+        .lines(choices.len() as i32)
+        .show(choices)?;
+
+    match res {
+        RofiResponse::Entry(ref s) if s == "Copy" => clipboard::copy(password),
+        RofiResponse::Entry(ref s) if s == "Type" => clipboard::type_text(password),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// How often and how recently items were used, keyed by provider and item id.
+pub struct History {
+    path: PathBuf,
+    entries: HashMap<String, Usage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Usage {
+    count: u32,
+    last_used: DateTime<Utc>,
+}
+
+impl History {
+    pub fn try_load(path: &Path) -> History {
+        let entries = match fs::read_to_string(path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    eprintln!("Could not deserialize history: {}", err);
+                    HashMap::new()
+                }
+            },
+            Err(_) => HashMap::new(),
+        };
+
+        History {
+            entries,
+            path: path.to_owned(),
+        }
+    }
+
+    pub fn key(provider: &str, id: &str) -> String {
+        format!("{}:{}", provider, id)
+    }
+
+    pub fn record(&mut self, key: &str) {
+        let usage = self.entries.entry(key.to_owned()).or_insert(Usage {
+            count: 0,
+            last_used: Utc::now(),
+        });
+        usage.count += 1;
+        usage.last_used = Utc::now();
+
+        let mut file = match File::create(&self.path) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Could not create history file: {}", err);
+                return;
+            }
+        };
+
+        let contents = serde_json::to_string(&self.entries).unwrap();
+        if let Err(err) = file.write_all(contents.as_bytes()) {
+            eprintln!("Writing history file failed: {}", err);
+        }
+    }
+
+    /// Frecency: the use count, decaying with the days since the last use.
+    pub fn score(&self, key: &str) -> f64 {
+        match self.entries.get(key) {
+            Some(usage) => {
+                let days = (Utc::now() - usage.last_used).num_hours() as f64 / 24.0;
+                usage.count as f64 / (1.0 + days)
+            }
+            None => 0.0,
+        }
+    }
+}
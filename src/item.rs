@@ -1,22 +1,321 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use url::{Host, Url};
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-#[derive(Serialize, Deserialize, Debug)]
 pub struct Item {
     pub id: String,
     pub title: String,
     pub fields: Vec<Field>,
+    #[serde(default)]
+    pub kind: Kind,
+    #[serde(default)]
+    pub uris: Vec<Uri>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Kind {
+    #[default]
+    Login,
+    Note,
+    Card,
+    Identity,
+}
+
+impl Kind {
+    /// Tag shown next to the title in the menu, logins are not tagged.
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            Kind::Login => None,
+            Kind::Note => Some("note"),
+            Kind::Card => Some("card"),
+            Kind::Identity => Some("identity"),
+        }
+    }
+}
+
+/// How an URI is matched, named after Bitwarden's URI match detection.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum UriMatch {
+    #[default]
+    Domain,
+    Host,
+    StartsWith,
+    Exact,
+    RegularExpression,
+    Never,
+}
+
+/// Second level labels that are part of the public suffix under country code
+/// domains, eg. the "co" of bbc.co.uk.
+const SECOND_LEVEL_SUFFIXES: &[&str] = &[
+    "ac", "co", "com", "edu", "gov", "gv", "ltd", "mil", "ne", "net", "nic", "or", "org", "plc",
+    "sch",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Uri {
+    pub uri: String,
+    #[serde(default, rename = "match")]
+    pub match_: UriMatch,
+}
+
+impl Uri {
+    /// Whether the URI matches a text describing a window, eg. its title.
+    ///
+    /// Window titles rarely contain full URLs, so domain matching also
+    /// accepts the bare name of the domain as a word, eg. "github" for
+    /// github.com.
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        let uri = self.uri.to_lowercase();
+        let words = || text.split(|c: char| c.is_whitespace() || c == '|');
+
+        match self.match_ {
+            UriMatch::Never => false,
+            UriMatch::Exact => words().any(|w| w == uri),
+            UriMatch::StartsWith => words().any(|w| w.starts_with(&uri)),
+            UriMatch::RegularExpression => Regex::new(&self.uri)
+                .map(|re| re.is_match(&text))
+                .unwrap_or(false),
+            UriMatch::Host => match self.host() {
+                Some(host) => text.contains(&host),
+                None => false,
+            },
+            UriMatch::Domain => match self.domain() {
+                Some((domain, name)) => {
+                    let is_name = name.chars().any(|c| c.is_alphabetic());
+                    text.contains(&domain)
+                        || is_name
+                            && text
+                                .split(|c: char| !c.is_alphanumeric() && c != '-')
+                                .any(|w| w == name)
+                }
+                // IP addresses and hosts like localhost have no domain.
+                None => self.host().is_some_and(|host| text.contains(&host)),
+            },
+        }
+    }
+
+    fn url(&self) -> Option<Url> {
+        match Url::parse(&self.uri) {
+            Ok(url) if url.has_host() => Some(url),
+            _ => Url::parse(&format!("http://{}", self.uri)).ok(),
+        }
+    }
+
+    fn host(&self) -> Option<String> {
+        let url = self.url()?;
+        let host = url.host_str()?.to_lowercase();
+        Some(match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host,
+        })
+    }
+
+    /// The registered domain and its name, eg. "bbc.co.uk" and "bbc" for
+    /// www.bbc.co.uk.
+    fn domain(&self) -> Option<(String, String)> {
+        let host = match self.url()?.host()? {
+            Host::Domain(host) => host.to_lowercase(),
+            _ => return None,
+        };
+        let labels: Vec<&str> = host.split('.').collect();
+        let len = match labels[..] {
+            [.., second, tld] if tld.len() == 2 && SECOND_LEVEL_SUFFIXES.contains(&second) => 3,
+            _ => 2,
+        };
+        if labels.len() < len {
+            return None;
+        }
+        let labels = &labels[labels.len() - len..];
+        Some((labels.join("."), labels[0].to_owned()))
+    }
+
+    /// URIs that can be opened in a browser.
+    pub fn is_web(&self) -> bool {
+        self.uri.starts_with("http://") || self.uri.starts_with("https://")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub id: String,
+    pub file_name: String,
+    /// Human readable, eg. "1.2 KB".
+    pub size: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Field {
     Username,
     Password,
+    /// Reading this field gives the TOTP secret or `otpauth://` URI, not
+    /// the code itself.
     Totp,
+    Notes,
     Other(String),
+    /// Like `Other`, but secret like the password.
+    Hidden(String),
+}
+
+impl Field {
+    pub fn name(&self) -> &str {
+        match self {
+            Field::Username => "username",
+            Field::Password => "password",
+            Field::Totp => "totp",
+            Field::Notes => "notes",
+            Field::Other(name) => name,
+            Field::Hidden(name) => name,
+        }
+    }
+
+    pub fn is_secret(&self) -> bool {
+        matches!(self, Field::Password | Field::Hidden(_))
+    }
+}
+
+/// What can be done with an item besides reading and editing its fields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemAction {
+    Move,
+    Delete,
+    ToggleFavorite,
+}
+
+impl ItemAction {
+    /// Menu row for the action on an item.
+    pub fn title(&self, item: &Item) -> &str {
+        match self {
+            ItemAction::Move => "move",
+            ItemAction::Delete => "delete",
+            ItemAction::ToggleFavorite if item.favorite => "unfavorite",
+            ItemAction::ToggleFavorite => "favorite",
+        }
+    }
+}
+
+/// Previous passwords of an item, the newest first.
+pub struct PasswordHistory {
+    /// When the current password was set.
+    pub revision_date: Option<DateTime<Utc>>,
+    pub passwords: Vec<OldPassword>,
+}
+
+pub struct OldPassword {
+    pub password: String,
+    /// When it was replaced.
+    pub last_used: DateTime<Utc>,
+}
+
+/// A login to create, as entered in the form.
+pub struct NewLogin {
+    pub name: String,
+    /// Folder path, separated by `/`.
+    pub folder: Option<String>,
+    pub username: Option<String>,
+    pub password: String,
+    pub uri: Option<String>,
 }
 
 pub struct Action {
     pub title: String,
     pub shortcut: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(uri: &str, match_: UriMatch, text: &str) -> bool {
+        Uri {
+            uri: uri.to_owned(),
+            match_,
+        }
+        .matches(text)
+    }
+
+    #[test]
+    fn domain() {
+        let domain = |uri, text| matches(uri, UriMatch::Domain, text);
+        assert!(domain(
+            "https://github.com/login",
+            "gist.github.com - Firefox"
+        ));
+        assert!(domain(
+            "https://github.com/login",
+            "GitHub - Mozilla Firefox"
+        ));
+        assert!(!domain(
+            "https://github.com/login",
+            "GitLab - Mozilla Firefox"
+        ));
+        assert!(domain("https://app.example.com:8443", "example.com"));
+
+        assert!(domain("https://www.bbc.co.uk/news", "BBC News - Firefox"));
+        assert!(domain("https://www.bbc.co.uk/news", "Sport - bbc.co.uk"));
+        assert!(!domain("https://www.bbc.co.uk/news", "shop.example.co.uk"));
+        assert!(!domain("https://www.bbc.co.uk/news", "Co op"));
+
+        assert!(domain("http://10.0.0.1:8080", "Router - 10.0.0.1:8080"));
+        assert!(!domain("http://10.0.0.1:8080", "Firefox 130.0.1"));
+        assert!(domain("http://localhost:3000", "localhost:3000/admin"));
+        assert!(!domain("http://localhost:3000", "localhost:8080"));
+    }
+
+    #[test]
+    fn host() {
+        let host = |uri, text| matches(uri, UriMatch::Host, text);
+        assert!(host(
+            "https://mail.example.com/inbox",
+            "Inbox - mail.example.com"
+        ));
+        assert!(!host("https://mail.example.com/inbox", "www.example.com"));
+        assert!(host("example.com:8443", "example.com:8443"));
+        assert!(!host("example.com:8443", "example.com"));
+    }
+
+    #[test]
+    fn starts_with_and_exact() {
+        let uri = "https://example.com/app";
+        assert!(matches(
+            uri,
+            UriMatch::StartsWith,
+            "Open https://example.com/app/settings"
+        ));
+        assert!(!matches(uri, UriMatch::StartsWith, "https://example.com/"));
+        assert!(matches(
+            uri,
+            UriMatch::Exact,
+            "App | https://example.com/app"
+        ));
+        assert!(!matches(
+            uri,
+            UriMatch::Exact,
+            "https://example.com/app/settings"
+        ));
+    }
+
+    #[test]
+    fn regular_expression_and_never() {
+        let re = |uri, text| matches(uri, UriMatch::RegularExpression, text);
+        assert!(re(r"^login - .*\.example\.com$", "Login - sso.example.com"));
+        assert!(!re(r"^login - .*\.example\.com$", "Login - example.org"));
+        // An invalid expression matches nothing.
+        assert!(!re("(", "("));
+        assert!(!matches(
+            "https://example.com",
+            UriMatch::Never,
+            "example.com"
+        ));
+    }
+}
@@ -3,7 +3,14 @@ extern crate lazy_static;
 
 pub mod app;
 pub mod cache;
+pub mod clipboard;
+pub mod generator;
+pub mod history;
 pub mod item;
+pub mod notify;
 pub mod provider;
 pub mod providers;
+pub mod report;
 pub mod rofi;
+pub mod totp;
+pub mod window;
@@ -1,6 +1,10 @@
 use anyhow::Result;
 use bitwarden_rofi::app::App;
+use std::env;
 
 fn main() -> Result<()> {
-    App::new()?.show()
+    match env::args().nth(1).as_deref() {
+        Some("report") => App::new()?.report(),
+        _ => App::new()?.show(),
+    }
 }
@@ -0,0 +1,14 @@
+use std::process::Command;
+
+/// Show a desktop notification using notify-send. Failing to do so is not
+/// worth failing for, so it is only logged.
+pub fn send(summary: &str, body: &str) {
+    match Command::new("notify-send")
+        .args(["--app-name", "bitwarden_rofi", summary, body])
+        .status()
+    {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("notify-send exited with {}", status),
+        Err(err) => eprintln!("notify-send failed: {}", err),
+    }
+}
@@ -1,12 +1,125 @@
 use crate::app::App;
-use crate::item::{Action, Field, Item};
-use anyhow::Result;
+use crate::generator::{self, Options};
+use crate::item::{Action, Attachment, Field, Item, ItemAction, NewLogin, PasswordHistory};
+use crate::report::Password;
+use anyhow::{anyhow, Result};
+use std::path::Path;
 
-pub type NewProvider = dyn Sync + Fn(&App, &str, serde_json::Value) -> Box<dyn Provider>;
+pub type NewProvider = dyn Send + Sync + Fn(&App, &str, serde_json::Value) -> Box<dyn Provider>;
 
 pub trait Provider {
     fn list_items(&mut self) -> Result<Vec<Item>>;
     fn read_field(&mut self, item: &Item, field: &Field) -> Result<String>;
+
+    /// Fields of an item, for providers that only know them after reading
+    /// the item.
+    fn list_fields(&mut self, item: &Item) -> Result<Vec<Field>> {
+        Ok(item.fields.clone())
+    }
+
     fn list_actions(&mut self) -> Result<Vec<Action>>;
-    fn do_action(&mut self, action: &Action);
+    fn do_action(&mut self, action: &Action) -> Result<()>;
+
+    // Optional capabilities. Providers without a notion of a locked vault or
+    // a remote to sync with can rely on these defaults.
+
+    fn lock(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn unlock(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_locked(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Short human readable status, eg. "unlocked".
+    fn status(&mut self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Generate a password, with the built-in generator unless the provider
+    /// has one of its own.
+    fn generate_password(&mut self, options: &Options) -> Result<String> {
+        generator::generate(options, None)
+    }
+
+    /// The passwords of all items, for the health report. Reads them one by
+    /// one unless the provider can do better.
+    fn list_passwords(&mut self) -> Result<Vec<Password>> {
+        let mut passwords = vec![];
+        for item in self.list_items()? {
+            if !self.list_fields(&item)?.contains(&Field::Password) {
+                continue;
+            }
+            let password = self.read_field(&item, &Field::Password)?;
+            passwords.push(Password {
+                item,
+                password,
+                revision_date: None,
+            });
+        }
+        Ok(passwords)
+    }
+
+    // Writing. Read-only providers keep these defaults.
+
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    /// Folders new items can be put in, as paths separated by `/`.
+    fn list_folders(&mut self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    fn create_item(&mut self, _login: &NewLogin) -> Result<Item> {
+        Err(anyhow!("Creating items is not supported"))
+    }
+
+    fn update_field(&mut self, _item: &Item, _field: &Field, _value: &str) -> Result<()> {
+        Err(anyhow!("Editing items is not supported"))
+    }
+
+    /// The item actions the provider supports.
+    fn item_actions(&self) -> Vec<ItemAction> {
+        vec![]
+    }
+
+    /// Move an item to a folder, `None` being the root.
+    fn move_item(&mut self, _item: &Item, _folder: Option<&str>) -> Result<()> {
+        Err(anyhow!("Moving items is not supported"))
+    }
+
+    fn delete_item(&mut self, _item: &Item) -> Result<()> {
+        Err(anyhow!("Deleting items is not supported"))
+    }
+
+    fn set_favorite(&mut self, _item: &Item, _favorite: bool) -> Result<()> {
+        Err(anyhow!("Favorites are not supported"))
+    }
+
+    fn has_password_history(&self) -> bool {
+        false
+    }
+
+    fn password_history(&mut self, _item: &Item) -> Result<PasswordHistory> {
+        Err(anyhow!("Password history is not supported"))
+    }
+
+    /// Write one of the `attachments` of an item to a file.
+    fn save_attachment(
+        &mut self,
+        _item: &Item,
+        _attachment: &Attachment,
+        _path: &Path,
+    ) -> Result<()> {
+        Err(anyhow!("Attachments are not supported"))
+    }
 }
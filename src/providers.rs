@@ -1,4 +1,15 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
 pub mod bitwarden;
 pub mod keyhub;
 pub mod password_store;
 pub mod terraform;
+
+/// Expand a leading `~/` in a configured path.
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
@@ -1,4 +1,11 @@
+mod cli;
+mod crypto;
+mod native;
 mod provider;
+mod send;
+mod serve;
 mod session;
+#[cfg(test)]
+mod stand_in;
 
 pub use provider::Bitwarden;
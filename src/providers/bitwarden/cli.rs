@@ -0,0 +1,351 @@
+use super::session::{Collection, Error, Folder, Item, Organization, Session, Status};
+use crate::generator::Options;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::str;
+
+/// Session that spawns the `bw` CLI for every call.
+pub struct Cli {
+    key: String,
+    appdata_dir: Option<PathBuf>,
+}
+
+/// A `bw` command using the given data dir instead of the global one, so
+/// several accounts can be logged in at the same time.
+pub fn bw(appdata_dir: Option<&Path>) -> Command {
+    let mut command = Command::new("bw");
+    if let Some(dir) = appdata_dir {
+        command.env("BITWARDENCLI_APPDATA_DIR", dir);
+    }
+    command
+}
+
+/// Error for a `bw` command that could not be started.
+pub fn spawn_error(err: io::Error) -> anyhow::Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => anyhow::Error::from(Error::BwMissing),
+        _ => err.into(),
+    }
+}
+
+/// The failure of a finished `bw` command from its exit code and stderr.
+fn check(output: Output) -> Result<Output> {
+    if output.status.success() {
+        return Ok(output);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let message = match stderr.trim() {
+        "" => String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr => stderr.to_owned(),
+    };
+    eprintln!("bw exited with {}: {}", output.status, message.trim());
+    Err(anyhow::Error::from(Error::from_message(&message)))
+}
+
+impl Cli {
+    pub fn open(key: &str, appdata_dir: Option<&Path>) -> Cli {
+        Cli {
+            key: key.to_string(),
+            appdata_dir: appdata_dir.map(Path::to_path_buf),
+        }
+    }
+
+    pub fn unlock(password: &str, appdata_dir: Option<&Path>) -> Result<Cli> {
+        let mut p = bw(appdata_dir)
+            .args(["unlock", "--raw"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error)?;
+
+        {
+            let input = password.as_bytes();
+            let stdin = p.stdin.as_mut().unwrap();
+            stdin.write_all(input)?;
+        }
+
+        let output = check(p.wait_with_output()?)?.stdout;
+        let key = str::from_utf8(&output)?.to_string();
+
+        if key.is_empty() {
+            return Err(anyhow::Error::from(Error::UnlockFailed));
+        }
+
+        Ok(Cli {
+            key,
+            appdata_dir: appdata_dir.map(Path::to_path_buf),
+        })
+    }
+
+    /// Status of `bw` without a session key, eg. to find out whether it is
+    /// logged in at all.
+    pub fn status_of(appdata_dir: Option<&Path>) -> Result<Status> {
+        Cli::open("", appdata_dir).status()
+    }
+
+    /// Point `bw` to a self-hosted server, only possible when logged out.
+    pub fn config_server(url: &str, appdata_dir: Option<&Path>) -> Result<()> {
+        Cli::open("", appdata_dir).call_str(&["config", "server", url])?;
+        Ok(())
+    }
+
+    /// Log in with the master password, which also unlocks the vault. For
+    /// two-step login `method` is the number of the provider as known by
+    /// `bw login --method`.
+    pub fn login(
+        email: &str,
+        password: &str,
+        method: Option<u8>,
+        code: Option<&str>,
+        appdata_dir: Option<&Path>,
+    ) -> Result<Cli> {
+        let mut command = bw(appdata_dir);
+        command
+            .args(["login", email, "--passwordenv", "BW_PASSWORD", "--raw"])
+            .env("BW_PASSWORD", password)
+            .env("BW_NOINTERACTION", "true");
+        if let Some(method) = method {
+            command.arg("--method").arg(method.to_string());
+        }
+        if let Some(code) = code {
+            command.args(["--code", code]);
+        }
+
+        let output = check(command.stdin(Stdio::null()).output().map_err(spawn_error)?)?;
+        let key = str::from_utf8(&output.stdout)?.trim().to_string();
+        if key.is_empty() {
+            return Err(anyhow::Error::from(Error::LoginFailed(
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            )));
+        }
+
+        Ok(Cli {
+            key,
+            appdata_dir: appdata_dir.map(Path::to_path_buf),
+        })
+    }
+
+    /// Log in with a personal API key. The vault remains locked.
+    pub fn login_apikey(
+        client_id: &str,
+        client_secret: &str,
+        appdata_dir: Option<&Path>,
+    ) -> Result<()> {
+        let output = bw(appdata_dir)
+            .args(["login", "--apikey"])
+            .env("BW_CLIENTID", client_id)
+            .env("BW_CLIENTSECRET", client_secret)
+            .env("BW_NOINTERACTION", "true")
+            .stdin(Stdio::null())
+            .output()
+            .map_err(spawn_error)?;
+
+        check(output)?;
+        Ok(())
+    }
+
+    /// Generate a password with `bw generate`, which needs no session.
+    pub fn generate(options: &Options, appdata_dir: Option<&Path>) -> Result<String> {
+        let mut args: Vec<String> = vec!["generate".to_owned()];
+        match options {
+            Options::Password {
+                length,
+                uppercase,
+                lowercase,
+                numbers,
+                special,
+            } => {
+                for (enabled, flag) in [
+                    (uppercase, "--uppercase"),
+                    (lowercase, "--lowercase"),
+                    (numbers, "--number"),
+                    (special, "--special"),
+                ]
+                .iter()
+                {
+                    if **enabled {
+                        args.push(flag.to_string());
+                    }
+                }
+                args.extend(vec!["--length".to_owned(), length.to_string()]);
+            }
+            Options::Passphrase { words, separator } => {
+                args.extend(vec![
+                    "--passphrase".to_owned(),
+                    "--words".to_owned(),
+                    words.to_string(),
+                    "--separator".to_owned(),
+                    separator.to_owned(),
+                ]);
+            }
+        }
+
+        let args: Vec<&str> = args.iter().map(|s| &s[..]).collect();
+        let password = Cli::open("", appdata_dir).call_str(&args)?;
+        Ok(password.trim().to_owned())
+    }
+
+    fn call_str(&self, args: &[&str]) -> Result<String> {
+        self.call_with_input(args, None)
+    }
+
+    /// Like `call_str`, writing `input` to the stdin of `bw`. Secrets go this
+    /// way rather than in the arguments, which other users can read.
+    fn call_with_input(&self, args: &[&str], input: Option<&str>) -> Result<String> {
+        let mut command = bw(self.appdata_dir.as_deref());
+        command
+            .args(args)
+            .stdin(match input {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // An empty key still counts as one for bw.
+        if !self.key.is_empty() {
+            command.env("BW_SESSION", &self.key);
+        }
+        let mut p = command.spawn().map_err(spawn_error)?;
+
+        if let Some(input) = input {
+            // Dropped right away, so bw sees the end of the input.
+            let mut stdin = p.stdin.take().unwrap();
+            stdin.write_all(input.as_bytes())?;
+        }
+
+        let p = check(p.wait_with_output()?)?;
+        let output = str::from_utf8(&p.stdout)?;
+        self.check_for_errors(output)?;
+        Ok(output.to_string())
+    }
+
+    fn call_json<T>(&self, args: &[&str]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        self.call_json_with_input(args, None)
+    }
+
+    fn call_json_with_input<T>(&self, args: &[&str], input: Option<&str>) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let output = self.call_with_input(args, input)?;
+        Ok(serde_json::from_str(&output)?)
+    }
+
+    fn check_for_errors(&self, output: &str) -> std::result::Result<(), Error> {
+        if output.starts_with("Failed to decrypt.") {
+            Err(Error::FailedToDecrypt)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Session for Cli {
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn lock(&self) -> Result<()> {
+        let resp = self.call_str(&["lock"])?;
+        match &resp[..] {
+            "Your vault is locked." => Ok(()),
+            _ => Err(anyhow::Error::from(Error::UnexpectedResponse(resp))),
+        }
+    }
+
+    fn sync(&self) -> Result<()> {
+        let resp = self.call_str(&["sync"])?;
+        match resp.trim() {
+            "Syncing complete." => Ok(()),
+            _ => Err(anyhow::Error::from(Error::UnexpectedResponse(resp))),
+        }
+    }
+
+    fn status(&self) -> Result<Status> {
+        self.call_json(&["status"])
+    }
+
+    fn list_folders(&self) -> Result<Vec<Folder>> {
+        self.call_json(&["list", "folders"])
+    }
+
+    fn list_organizations(&self) -> Result<Vec<Organization>> {
+        self.call_json(&["list", "organizations"])
+    }
+
+    fn list_collections(&self) -> Result<Vec<Collection>> {
+        self.call_json(&["list", "collections"])
+    }
+
+    fn list_items(&self) -> Result<Vec<Item>> {
+        self.call_json(&["list", "items"])
+    }
+
+    fn get_item(&self, id: &str) -> Result<Item> {
+        self.call_json(&["get", "item", id])
+    }
+
+    fn read_field(&self, id: &str, field: &str) -> Result<String> {
+        self.call_str(&["get", id, field])
+    }
+
+    fn get_raw_item(&self, id: &str) -> Result<Value> {
+        self.call_json(&["get", "item", id])
+    }
+
+    fn create_item(&self, item: &Value) -> Result<Item> {
+        self.call_json_with_input(&["create", "item"], Some(&encode(item)))
+    }
+
+    fn edit_item(&self, id: &str, item: &Value) -> Result<Item> {
+        self.call_json_with_input(&["edit", "item", id], Some(&encode(item)))
+    }
+
+    fn create_folder(&self, name: &str) -> Result<Folder> {
+        let folder = serde_json::json!({ "name": name });
+        self.call_json_with_input(&["create", "folder"], Some(&encode(&folder)))
+    }
+
+    fn get_attachment(&self, item_id: &str, id: &str, output: &Path) -> Result<()> {
+        let output = output.to_string_lossy();
+        self.call_str(&[
+            "get",
+            "attachment",
+            id,
+            "--itemid",
+            item_id,
+            "--output",
+            &output,
+        ])?;
+        Ok(())
+    }
+
+    fn create_send(&self, send: &Value, file: Option<&Path>) -> Result<Value> {
+        let file = file.map(|f| f.to_string_lossy().into_owned());
+        let mut args = vec!["send", "create"];
+        if let Some(file) = &file {
+            args.extend(&["--file", file]);
+        }
+        self.call_json_with_input(&args, Some(&encode(send)))
+    }
+
+    fn delete_item(&self, id: &str) -> Result<()> {
+        self.call_str(&["delete", "item", id])?;
+        Ok(())
+    }
+}
+
+/// Objects are passed to `bw create` and `bw edit` as base64 encoded JSON on
+/// stdin, the same as `bw encode` does.
+fn encode(object: &Value) -> String {
+    base64::encode(object.to_string())
+}
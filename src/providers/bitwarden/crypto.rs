@@ -0,0 +1,285 @@
+//! Bitwarden key derivation and cipher string encryption.
+//!
+//! https://bitwarden.com/help/bitwarden-security-white-paper/
+
+use super::session::Error;
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use anyhow::Result;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::{Oaep, RsaPrivateKey};
+use sha2::{Digest, Sha256};
+
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+type HmacSha256 = Hmac<Sha256>;
+
+pub enum Kdf {
+    Pbkdf2 {
+        iterations: u32,
+    },
+    Argon2id {
+        iterations: u32,
+        memory_mib: u32,
+        parallelism: u32,
+    },
+}
+
+/// Derive the master key from the master password.
+pub fn master_key(password: &str, email: &str, kdf: &Kdf) -> Result<Vec<u8>> {
+    let email = email.trim().to_lowercase();
+    let mut key = vec![0u8; 32];
+
+    match *kdf {
+        Kdf::Pbkdf2 { iterations } => {
+            pbkdf2::pbkdf2_hmac::<Sha256>(
+                password.as_bytes(),
+                email.as_bytes(),
+                iterations,
+                &mut key,
+            );
+        }
+        Kdf::Argon2id {
+            iterations,
+            memory_mib,
+            parallelism,
+        } => {
+            let params = argon2::Params::new(memory_mib * 1024, iterations, parallelism, Some(32))
+                .map_err(|err| anyhow::anyhow!("Invalid argon2 parameters: {}", err))?;
+            let argon2 =
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+            let salt = Sha256::digest(email.as_bytes());
+            argon2
+                .hash_password_into(password.as_bytes(), &salt, &mut key)
+                .map_err(|err| anyhow::anyhow!("Argon2 failed: {}", err))?;
+        }
+    }
+
+    Ok(key)
+}
+
+/// Hash of the master key that is sent to the server to authenticate.
+pub fn master_password_hash(master_key: &[u8], password: &str) -> String {
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(master_key, password.as_bytes(), 1, &mut hash);
+    base64::encode(hash)
+}
+
+/// AES-256 encryption key together with its HMAC-SHA256 key.
+pub struct SymmetricKey {
+    enc: Vec<u8>,
+    mac: Vec<u8>,
+}
+
+impl SymmetricKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<SymmetricKey> {
+        if bytes.len() != 64 {
+            return Err(Error::FailedToDecrypt.into());
+        }
+        Ok(SymmetricKey {
+            enc: bytes[..32].to_vec(),
+            mac: bytes[32..].to_vec(),
+        })
+    }
+
+    /// A new random key.
+    pub fn generate() -> SymmetricKey {
+        let mut bytes = [0u8; 64];
+        OsRng.fill_bytes(&mut bytes);
+        SymmetricKey {
+            enc: bytes[..32].to_vec(),
+            mac: bytes[32..].to_vec(),
+        }
+    }
+
+    /// Stretch the master key into the key that protects the user key.
+    pub fn stretch(master_key: &[u8]) -> Result<SymmetricKey> {
+        let hkdf = Hkdf::<Sha256>::from_prk(master_key).map_err(|_| Error::FailedToDecrypt)?;
+        let mut enc = vec![0u8; 32];
+        let mut mac = vec![0u8; 32];
+        hkdf.expand(b"enc", &mut enc)
+            .map_err(|_| Error::FailedToDecrypt)?;
+        hkdf.expand(b"mac", &mut mac)
+            .map_err(|_| Error::FailedToDecrypt)?;
+        Ok(SymmetricKey { enc, mac })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.enc[..], &self.mac[..]].concat()
+    }
+
+    /// Decrypt a type 2 (AES-CBC-256 with HMAC-SHA256) cipher string.
+    pub fn decrypt(&self, cipher_string: &str) -> Result<Vec<u8>> {
+        let parts = match cipher_string.split_once('.') {
+            Some(("2", rest)) => rest.split('|').collect::<Vec<_>>(),
+            _ => return Err(Error::FailedToDecrypt.into()),
+        };
+        if parts.len() != 3 {
+            return Err(Error::FailedToDecrypt.into());
+        }
+
+        let iv = base64::decode(parts[0])?;
+        let data = base64::decode(parts[1])?;
+        let mac = base64::decode(parts[2])?;
+
+        let mut hmac = HmacSha256::new_from_slice(&self.mac)?;
+        hmac.update(&iv);
+        hmac.update(&data);
+        hmac.verify_slice(&mac)
+            .map_err(|_| Error::FailedToDecrypt)?;
+
+        Aes256CbcDec::new_from_slices(&self.enc, &iv)
+            .map_err(|_| Error::FailedToDecrypt)?
+            .decrypt_padded_vec_mut::<Pkcs7>(&data)
+            .map_err(|_| Error::FailedToDecrypt.into())
+    }
+
+    pub fn decrypt_str(&self, cipher_string: &str) -> Result<String> {
+        Ok(String::from_utf8(self.decrypt(cipher_string)?)?)
+    }
+
+    /// Encrypt into a type 2 cipher string.
+    pub fn encrypt(&self, data: &[u8]) -> Result<String> {
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut iv);
+
+        let encrypted = Aes256CbcEnc::new_from_slices(&self.enc, &iv)
+            .map_err(|_| anyhow::anyhow!("Invalid key"))?
+            .encrypt_padded_vec_mut::<Pkcs7>(data);
+
+        let mut hmac = HmacSha256::new_from_slice(&self.mac)?;
+        hmac.update(&iv);
+        hmac.update(&encrypted);
+        let mac = hmac.finalize().into_bytes();
+
+        Ok(format!(
+            "2.{}|{}|{}",
+            base64::encode(iv),
+            base64::encode(encrypted),
+            base64::encode(mac)
+        ))
+    }
+}
+
+/// RSA key that protects the organization keys.
+pub struct PrivateKey(RsaPrivateKey);
+
+impl PrivateKey {
+    pub fn from_der(der: &[u8]) -> Result<PrivateKey> {
+        let key = RsaPrivateKey::from_pkcs8_der(der).map_err(|_| Error::FailedToDecrypt)?;
+        Ok(PrivateKey(key))
+    }
+
+    /// Decrypt a type 3 (RSA-OAEP-SHA256) or type 4 (RSA-OAEP-SHA1) cipher string.
+    pub fn decrypt(&self, cipher_string: &str) -> Result<Vec<u8>> {
+        let (padding, data) = match cipher_string.split_once('.') {
+            Some(("3", data)) | Some(("5", data)) => (Oaep::new::<Sha256>(), data),
+            Some(("4", data)) | Some(("6", data)) => (Oaep::new::<sha1::Sha1>(), data),
+            _ => return Err(Error::FailedToDecrypt.into()),
+        };
+        // Types 5 and 6 carry a (deprecated) MAC after the data.
+        let data = base64::decode(data.split('|').next().unwrap_or_default())?;
+
+        self.0
+            .decrypt(padding, &data)
+            .map_err(|_| Error::FailedToDecrypt.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Computed with the Python cryptography package.
+    const EMAIL: &str = "user@example.com";
+    const PASSWORD: &str = "correct horse battery staple";
+    const PBKDF2_KEY: &str = "52ce2d33e007a7c15b3e3083c551fcff97da83045fa80cc6bbbd2cbc52c6f084";
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn pbkdf2_master_key() {
+        let kdf = Kdf::Pbkdf2 { iterations: 5000 };
+        assert_eq!(hex(&master_key(PASSWORD, EMAIL, &kdf).unwrap()), PBKDF2_KEY);
+        // The email is the salt after trimming and lowercasing.
+        let key = master_key(PASSWORD, " User@Example.com ", &kdf).unwrap();
+        assert_eq!(hex(&key), PBKDF2_KEY);
+    }
+
+    #[test]
+    fn pbkdf2_sha256_vector() {
+        // The common PBKDF2-HMAC-SHA256 vector for "password" and "salt".
+        let key = master_key("password", "salt", &Kdf::Pbkdf2 { iterations: 1 }).unwrap();
+        assert_eq!(
+            hex(&key),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+    }
+
+    #[test]
+    fn argon2id_master_key() {
+        let kdf = Kdf::Argon2id {
+            iterations: 3,
+            memory_mib: 64,
+            parallelism: 4,
+        };
+        assert_eq!(
+            hex(&master_key(PASSWORD, EMAIL, &kdf).unwrap()),
+            "26034d0eaf9c2631caec3e6cc7f47603bee30bf3d86f8d79968d05fa3245f702"
+        );
+    }
+
+    #[test]
+    fn password_hash() {
+        assert_eq!(
+            master_password_hash(&unhex(PBKDF2_KEY), PASSWORD),
+            "0FMeontUyfpu9Ga/DvERL9LMAXg9KB82VK6UqHdnKko="
+        );
+    }
+
+    #[test]
+    fn hkdf_stretch() {
+        let key = SymmetricKey::stretch(&unhex(PBKDF2_KEY)).unwrap();
+        assert_eq!(
+            hex(&key.enc),
+            "83b79d8c4f57f2e6ab04e4427c3fcc0d9bdeff981254a535a3df2bd2a4df84a7"
+        );
+        assert_eq!(
+            hex(&key.mac),
+            "016149a9eb912a3d9a41c4b3878a833e3cac88feef99dc4b1a6258716113e881"
+        );
+    }
+
+    #[test]
+    fn decrypt_cipher_string() {
+        let key = SymmetricKey::stretch(&unhex(PBKDF2_KEY)).unwrap();
+        let cipher_string = "2.AAECAwQFBgcICQoLDA0ODw==|WRG7iYCwvvQED9SiXxo1Uw==|\
+                             BmeCrJ/ij/UnbOl4JcTdr6AMY8+K5CTOMO0T+CeEelQ=";
+        assert_eq!(key.decrypt_str(cipher_string).unwrap(), "a secret note");
+
+        // A changed MAC is refused.
+        let tampered = cipher_string.replace("BmeC", "AmeC");
+        assert!(key.decrypt(&tampered).is_err());
+    }
+
+    #[test]
+    fn encrypt_round_trip() {
+        let key = SymmetricKey::generate();
+        let cipher_string = key.encrypt(b"some data").unwrap();
+        assert_eq!(key.decrypt(&cipher_string).unwrap(), b"some data");
+        assert!(SymmetricKey::generate().decrypt(&cipher_string).is_err());
+    }
+}
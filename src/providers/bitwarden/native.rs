@@ -0,0 +1,865 @@
+use super::crypto::{self, Kdf, PrivateKey, SymmetricKey};
+use super::session::{
+    null_as_empty, Collection, Error, Folder, Item, Organization as SessionOrganization, Session,
+    Status,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_SERVER_URL: &str = "https://vault.bitwarden.com";
+
+const CLIENT_ID: &str = "cli";
+const DEVICE_NAME: &str = "bitwarden_rofi";
+/// Linux CLI, see DeviceType in the Bitwarden server.
+const DEVICE_TYPE: &str = "25";
+
+/// Number of the email provider in TwoFactorProviderType.
+pub const TWO_STEP_EMAIL: u8 = 1;
+
+/// Session that talks to a Bitwarden (or Vaultwarden) server directly and
+/// decrypts the vault locally, without the `bw` CLI.
+///
+/// Like the session key of `bw`, the key kept in the keyring is a random
+/// key. It only decrypts the state file, which holds the keys of the vault.
+pub struct Native {
+    key: String,
+    server: Server,
+    state_file: PathBuf,
+    token: Token,
+    vault: RefCell<Option<Vault>>,
+}
+
+/// A second factor for logging in, once the server asked for one.
+pub enum SecondFactor {
+    /// Code of a two-step login provider, by its number in
+    /// TwoFactorProviderType.
+    TwoStep { provider: u8, code: String },
+    /// Code emailed to verify a new device.
+    NewDevice(String),
+}
+
+struct Server {
+    url: String,
+    identity_url: String,
+    api_url: String,
+}
+
+/// Everything needed to reopen a session, stored encrypted in the state
+/// file.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Token {
+    refresh_token: String,
+    user_key: String,
+    private_key: Option<String>,
+}
+
+struct Vault {
+    access_token: String,
+    last_sync: DateTime<Utc>,
+    user_email: String,
+    user_id: String,
+    folders: Vec<Folder>,
+    organizations: Vec<SessionOrganization>,
+    collections: Vec<Collection>,
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Prelogin {
+    kdf: i32,
+    kdf_iterations: u32,
+    kdf_memory: Option<u32>,
+    kdf_parallelism: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TokenResponse {
+    // The OAuth fields are snake_case, the Bitwarden ones are not.
+    #[serde(alias = "access_token")]
+    access_token: String,
+    #[serde(alias = "refresh_token")]
+    refresh_token: String,
+    key: Option<String>,
+    private_key: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    id: String,
+    email: String,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    organizations: Vec<Organization>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Organization {
+    id: String,
+    name: String,
+    key: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SyncResponse {
+    profile: Profile,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    folders: Vec<Value>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    collections: Vec<Value>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    ciphers: Vec<Value>,
+}
+
+impl Server {
+    fn new(url: &str) -> Server {
+        let url = url.trim_end_matches('/').to_owned();
+        // The cloud vault serves the identity and api on separate hosts.
+        let (identity_url, api_url) = if url == DEFAULT_SERVER_URL {
+            (
+                "https://identity.bitwarden.com".to_owned(),
+                "https://api.bitwarden.com".to_owned(),
+            )
+        } else {
+            (format!("{}/identity", url), format!("{}/api", url))
+        };
+
+        Server {
+            url,
+            identity_url,
+            api_url,
+        }
+    }
+}
+
+impl Native {
+    pub fn open(server_url: &str, key: &str, state_file: &Path) -> Result<Native> {
+        let session_key = base64::decode(key).map_err(|_| Error::FailedToDecrypt)?;
+        let session_key = SymmetricKey::from_bytes(&session_key)?;
+        // Gone after locking.
+        let state = fs::read_to_string(state_file).map_err(|_| Error::FailedToDecrypt)?;
+        let token: Token = serde_json::from_slice(&session_key.decrypt(state.trim())?)
+            .map_err(|_| Error::FailedToDecrypt)?;
+
+        Ok(Native {
+            key: key.to_owned(),
+            server: Server::new(server_url),
+            state_file: state_file.to_path_buf(),
+            token,
+            vault: RefCell::new(None),
+        })
+    }
+
+    /// Log in with the master password. Fails with `TwoStepRequired` or
+    /// `NewDeviceVerification` when the server wants a second factor, to
+    /// be passed on the next attempt.
+    pub fn unlock(
+        server_url: &str,
+        email: &str,
+        password: &str,
+        second_factor: Option<&SecondFactor>,
+        state_file: &Path,
+    ) -> Result<Native> {
+        let server = Server::new(server_url);
+        let (master_key, password_hash) = derive_keys(&server, email, password)?;
+        let device_identifier = device_identifier(email);
+
+        let mut form = vec![
+            ("grant_type", "password"),
+            ("username", email),
+            ("password", &password_hash),
+            ("scope", "api offline_access"),
+            ("client_id", CLIENT_ID),
+            ("deviceType", DEVICE_TYPE),
+            ("deviceIdentifier", &device_identifier),
+            ("deviceName", DEVICE_NAME),
+        ];
+        let provider;
+        match second_factor {
+            Some(SecondFactor::TwoStep {
+                provider: number,
+                code,
+            }) => {
+                provider = number.to_string();
+                form.extend(vec![
+                    ("twoFactorToken", &code[..]),
+                    ("twoFactorProvider", &provider),
+                    ("twoFactorRemember", "0"),
+                ]);
+            }
+            Some(SecondFactor::NewDevice(code)) => form.push(("newDeviceOtp", code)),
+            None => {}
+        }
+
+        let response = token_response(
+            ureq::post(&format!("{}/connect/token", server.identity_url))
+                .set(
+                    "Auth-Email",
+                    &base64::encode_config(email, base64::URL_SAFE_NO_PAD),
+                )
+                .send_form(&form),
+        )?;
+
+        let stretched_key = SymmetricKey::stretch(&master_key)?;
+        let user_key = match response.key {
+            Some(key) => SymmetricKey::from_bytes(&stretched_key.decrypt(&key)?)?,
+            None => return Err(Error::UnlockFailed.into()),
+        };
+        let private_key = match response.private_key {
+            Some(key) => Some(base64::encode(user_key.decrypt(&key)?)),
+            None => None,
+        };
+
+        let token = Token {
+            refresh_token: response.refresh_token,
+            user_key: base64::encode(user_key.to_bytes()),
+            private_key,
+        };
+        let session_key = SymmetricKey::generate();
+        save_state(
+            state_file,
+            &session_key.encrypt(&serde_json::to_vec(&token)?)?,
+        )?;
+
+        let session = Native {
+            key: base64::encode(session_key.to_bytes()),
+            server,
+            state_file: state_file.to_path_buf(),
+            token,
+            vault: RefCell::new(None),
+        };
+        session.fetch(Some(response.access_token))?;
+        Ok(session)
+    }
+
+    /// Have the server email a two-step login code.
+    pub fn send_email_code(server_url: &str, email: &str, password: &str) -> Result<()> {
+        let server = Server::new(server_url);
+        let (_, password_hash) = derive_keys(&server, email, password)?;
+
+        let result = ureq::post(&format!("{}/two-factor/send-email-login", server.api_url))
+            .send_json(serde_json::json!({
+                "email": email,
+                "masterPasswordHash": password_hash,
+                "deviceIdentifier": device_identifier(email),
+            }));
+        match result {
+            // Answered without a body.
+            Ok(_) => Ok(()),
+            Err(err) => from_response::<Value>(Err(err)).map(|_| ()),
+        }
+    }
+
+    /// Revoke the refresh token, which would otherwise stay valid for a long
+    /// time.
+    fn revoke(&self) -> Result<()> {
+        let result = ureq::post(&format!("{}/connect/revocation", self.server.identity_url))
+            .send_form(&[
+                ("token", &self.token.refresh_token),
+                ("token_type_hint", "refresh_token"),
+                ("client_id", CLIENT_ID),
+            ]);
+        match result {
+            Ok(_) => Ok(()),
+            Err(err) => from_response::<Value>(Err(err)).map(|_| ()),
+        }
+    }
+
+    /// Refresh the access token when needed and download the vault.
+    fn fetch(&self, access_token: Option<String>) -> Result<()> {
+        let access_token = match access_token {
+            Some(access_token) => access_token,
+            None => self.refresh()?,
+        };
+
+        let response: Value = from_response(
+            ureq::get(&format!("{}/sync", self.server.api_url))
+                .set("Authorization", &format!("Bearer {}", access_token))
+                .call(),
+        )?;
+        let response: SyncResponse = serde_json::from_value(camel_case_keys(response))?;
+
+        let user_key = SymmetricKey::from_bytes(&base64::decode(&self.token.user_key)?)?;
+        let org_keys = self.org_keys(&response.profile)?;
+
+        let folders = response
+            .folders
+            .into_iter()
+            .map(|mut folder| {
+                decrypt_values(&mut folder, &user_key)?;
+                folder["object"] = Value::from("folder");
+                Ok(serde_json::from_value(folder)?)
+            })
+            .collect::<Result<Vec<Folder>>>()?;
+
+        let organizations = response
+            .profile
+            .organizations
+            .iter()
+            .map(|org| SessionOrganization {
+                object: "organization".to_owned(),
+                id: org.id.clone(),
+                name: org.name.clone(),
+            })
+            .collect();
+
+        let mut collections = vec![];
+        for mut collection in response.collections.into_iter() {
+            let key = match collection["organizationId"].as_str() {
+                Some(org_id) => org_keys.get(org_id),
+                None => None,
+            };
+            if let Some(key) = key {
+                decrypt_values(&mut collection, key)?;
+                collection["object"] = Value::from("collection");
+                collections.push(serde_json::from_value(collection)?);
+            }
+        }
+
+        let mut items = vec![];
+        for mut cipher in response.ciphers.into_iter() {
+            let key = match cipher["organizationId"].as_str() {
+                Some(org_id) => match org_keys.get(org_id) {
+                    Some(key) => key,
+                    None => {
+                        eprintln!("bitwarden: No key for organization {}", org_id);
+                        continue;
+                    }
+                },
+                None => &user_key,
+            };
+
+            // Newer ciphers are encrypted with their own key.
+            let cipher_key = match cipher["key"].as_str() {
+                Some(cipher_key) => Some(SymmetricKey::from_bytes(&key.decrypt(cipher_key)?)?),
+                None => None,
+            };
+
+            decrypt_values(&mut cipher, cipher_key.as_ref().unwrap_or(key))?;
+            cipher["object"] = Value::from("item");
+            items.push(serde_json::from_value(cipher)?);
+        }
+
+        *self.vault.borrow_mut() = Some(Vault {
+            access_token,
+            last_sync: Utc::now(),
+            user_email: response.profile.email,
+            user_id: response.profile.id,
+            folders,
+            organizations,
+            collections,
+            items,
+        });
+
+        Ok(())
+    }
+
+    fn refresh(&self) -> Result<String> {
+        let response: TokenResponse = from_response(
+            ureq::post(&format!("{}/connect/token", self.server.identity_url)).send_form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", CLIENT_ID),
+                ("refresh_token", &self.token.refresh_token),
+            ]),
+        )?;
+        Ok(response.access_token)
+    }
+
+    fn org_keys(&self, profile: &Profile) -> Result<HashMap<String, SymmetricKey>> {
+        let mut keys = HashMap::new();
+
+        let private_key = match &self.token.private_key {
+            Some(der) => PrivateKey::from_der(&base64::decode(der)?)?,
+            None => return Ok(keys),
+        };
+
+        for org in profile.organizations.iter() {
+            if let Some(key) = &org.key {
+                let key = SymmetricKey::from_bytes(&private_key.decrypt(key)?)?;
+                keys.insert(org.id.clone(), key);
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn with_vault<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Vault) -> T,
+    {
+        if self.vault.borrow().is_none() {
+            self.fetch(None)?;
+        }
+        Ok(f(self.vault.borrow().as_ref().unwrap()))
+    }
+}
+
+impl Session for Native {
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn lock(&self) -> Result<()> {
+        *self.vault.borrow_mut() = None;
+        if let Err(err) = self.revoke() {
+            eprintln!("bitwarden: Failed to revoke the refresh token: {}", err);
+        }
+        match fs::remove_file(&self.state_file) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn sync(&self) -> Result<()> {
+        let access_token = self
+            .vault
+            .borrow()
+            .as_ref()
+            .map(|vault| vault.access_token.clone());
+        self.fetch(access_token)
+    }
+
+    fn status(&self) -> Result<Status> {
+        if self.vault.borrow().is_none() {
+            if let Err(err) = self.fetch(None) {
+                eprintln!("bitwarden: Could not open session: {}", err);
+                return Ok(Status {
+                    server_url: Some(self.server.url.clone()),
+                    last_sync: None,
+                    user_email: None,
+                    user_id: None,
+                    status: "locked".to_owned(),
+                });
+            }
+        }
+
+        self.with_vault(|vault| Status {
+            server_url: Some(self.server.url.clone()),
+            last_sync: Some(vault.last_sync),
+            user_email: Some(vault.user_email.clone()),
+            user_id: Some(vault.user_id.clone()),
+            status: "unlocked".to_owned(),
+        })
+    }
+
+    fn list_folders(&self) -> Result<Vec<Folder>> {
+        self.with_vault(|vault| vault.folders.clone())
+    }
+
+    fn list_organizations(&self) -> Result<Vec<SessionOrganization>> {
+        self.with_vault(|vault| vault.organizations.clone())
+    }
+
+    fn list_collections(&self) -> Result<Vec<Collection>> {
+        self.with_vault(|vault| vault.collections.clone())
+    }
+
+    fn list_items(&self) -> Result<Vec<Item>> {
+        self.with_vault(|vault| vault.items.clone())
+    }
+
+    fn get_item(&self, id: &str) -> Result<Item> {
+        let item = self.with_vault(|vault| vault.items.iter().find(|i| i.id == id).cloned())?;
+        item.ok_or_else(|| Error::UnexpectedResponse(format!("Item {} not found", id)).into())
+    }
+
+    fn read_field(&self, id: &str, field: &str) -> Result<String> {
+        let value = self.with_vault(|vault| {
+            let item = vault.items.iter().find(|i| i.id == id)?;
+            let login = item.login.as_ref();
+            match field {
+                "username" => login.and_then(|l| l.username.clone()),
+                "password" => login.and_then(|l| l.password.clone()),
+                "notes" => item.notes.clone(),
+                _ => None,
+            }
+        })?;
+
+        value.ok_or_else(|| {
+            Error::UnexpectedResponse(format!("Field {} not found on {}", field, id)).into()
+        })
+    }
+}
+
+/// The master key and the hash of it that authenticates, with the KDF the
+/// server has for the account.
+fn derive_keys(server: &Server, email: &str, password: &str) -> Result<(Vec<u8>, String)> {
+    let prelogin: Prelogin = from_response(
+        ureq::post(&format!("{}/accounts/prelogin", server.identity_url))
+            .send_json(serde_json::json!({ "email": email })),
+    )?;
+    let kdf = match prelogin.kdf {
+        0 => Kdf::Pbkdf2 {
+            iterations: prelogin.kdf_iterations,
+        },
+        1 => Kdf::Argon2id {
+            iterations: prelogin.kdf_iterations,
+            memory_mib: prelogin.kdf_memory.unwrap_or(64),
+            parallelism: prelogin.kdf_parallelism.unwrap_or(4),
+        },
+        kdf => return Err(Error::UnexpectedResponse(format!("Unsupported KDF {}", kdf)).into()),
+    };
+
+    let master_key = crypto::master_key(password, email, &kdf)?;
+    let password_hash = crypto::master_password_hash(&master_key, password);
+    Ok((master_key, password_hash))
+}
+
+/// The answer to a login, which asks for a second factor with a 400.
+fn token_response(
+    result: std::result::Result<ureq::Response, ureq::Error>,
+) -> Result<TokenResponse> {
+    let response = match result {
+        Err(ureq::Error::Status(400, response)) => response,
+        result => return from_response(result),
+    };
+
+    let body = response.into_string().unwrap_or_default();
+    let value = serde_json::from_str(&body)
+        .map(camel_case_keys)
+        .unwrap_or(Value::Null);
+    if let Some(providers) = value["twoFactorProviders2"].as_object() {
+        let providers = providers.keys().filter_map(|p| p.parse().ok()).collect();
+        return Err(Error::TwoStepRequired(providers).into());
+    }
+
+    let message = value["errorModel"]["message"]
+        .as_str()
+        .or_else(|| value["error_description"].as_str())
+        .unwrap_or(&body);
+    if message
+        .to_lowercase()
+        .contains("new device verification required")
+    {
+        return Err(Error::NewDeviceVerification.into());
+    }
+    Err(match Error::from_message(message) {
+        Error::UnexpectedResponse(_) => Error::UnexpectedResponse(format!("400: {}", body)),
+        err => err,
+    }
+    .into())
+}
+
+/// Write the encrypted state, readable only by the user.
+fn save_state(path: &Path, state: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(state.as_bytes())?;
+    Ok(())
+}
+
+fn from_response<T>(result: std::result::Result<ureq::Response, ureq::Error>) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    match result {
+        Ok(response) => {
+            let value: Value = response.into_json()?;
+            Ok(serde_json::from_value(camel_case_keys(value))?)
+        }
+        Err(ureq::Error::Status(429, _)) => Err(Error::RateLimited.into()),
+        Err(ureq::Error::Status(code, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            Err(match Error::from_message(&body) {
+                Error::UnexpectedResponse(_) => {
+                    Error::UnexpectedResponse(format!("{}: {}", code, body))
+                }
+                err => err,
+            }
+            .into())
+        }
+        Err(ureq::Error::Transport(err)) => Err(Error::Network(err.to_string()).into()),
+    }
+}
+
+/// Older servers answer in PascalCase, newer ones in camelCase.
+fn camel_case_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    let mut chars = k.chars();
+                    let k = match chars.next() {
+                        Some(c) => c.to_lowercase().chain(chars).collect(),
+                        None => k,
+                    };
+                    (k, camel_case_keys(v))
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(camel_case_keys).collect()),
+        value => value,
+    }
+}
+
+/// Decrypt every cipher string in the value, leaving the result shaped like
+/// the output of `bw list`.
+fn decrypt_values(value: &mut Value, key: &SymmetricKey) -> Result<()> {
+    match value {
+        Value::String(s) if is_cipher_string(s) => {
+            *s = key.decrypt_str(s)?;
+        }
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                // The cipher key is already used to decrypt the rest.
+                if k != "key" {
+                    decrypt_values(v, key)?;
+                }
+            }
+        }
+        Value::Array(values) => {
+            for v in values.iter_mut() {
+                decrypt_values(v, key)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_cipher_string(s: &str) -> bool {
+    s.starts_with("2.") && s.matches('|').count() == 2
+}
+
+/// A stable device id per account, so the server does not see a new device
+/// on every login.
+fn device_identifier(email: &str) -> String {
+    let hash = Sha256::digest(format!("{}:{}", DEVICE_NAME, email).as_bytes());
+    let hex: String = hash[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stand_in::{Request, StandIn};
+    use super::*;
+    use std::collections::HashMap;
+
+    const EMAIL: &str = "user@example.com";
+    const PASSWORD: &str = "correct horse battery staple";
+    const REFRESH_TOKEN: &str = "refresh-token";
+
+    /// A Bitwarden server with one login, asking for the given second factor
+    /// before handing out tokens.
+    fn server(second_factor: Option<&'static str>) -> StandIn {
+        let master_key =
+            crypto::master_key(PASSWORD, EMAIL, &Kdf::Pbkdf2 { iterations: 5000 }).unwrap();
+        let password_hash = crypto::master_password_hash(&master_key, PASSWORD);
+        let user_key = SymmetricKey::generate();
+        let protected_key = SymmetricKey::stretch(&master_key)
+            .unwrap()
+            .encrypt(&user_key.to_bytes())
+            .unwrap();
+        let encrypt = |s: &str| user_key.encrypt(s.as_bytes()).unwrap();
+        let sync = serde_json::json!({
+            "Profile": { "Id": "user-id", "Email": EMAIL, "Organizations": [] },
+            "Folders": [],
+            "Collections": [],
+            "Ciphers": [{
+                "Id": "1",
+                "Name": encrypt("example"),
+                "Type": 1,
+                "Favorite": false,
+                "CollectionIds": [],
+                "RevisionDate": "2024-01-01T00:00:00.000Z",
+                // Sent as null rather than left out when empty.
+                "Fields": null,
+                "Login": {
+                    "Uris": null,
+                    "Username": encrypt("user"),
+                    "Password": encrypt("hunter2"),
+                },
+            }],
+        })
+        .to_string();
+
+        StandIn::start(move |request: &Request| match &request.path[..] {
+            "/identity/accounts/prelogin" => (200, r#"{"kdf":0,"kdfIterations":5000}"#.to_owned()),
+            "/identity/connect/token" => {
+                let form: HashMap<_, _> = url::form_urlencoded::parse(request.body.as_bytes())
+                    .into_owned()
+                    .collect();
+                if form["grant_type"] == "refresh_token" {
+                    assert_eq!(form["refresh_token"], REFRESH_TOKEN);
+                    return (200, token(None));
+                }
+                if form["password"] != password_hash {
+                    return (
+                        400,
+                        r#"{"error":"invalid_grant","ErrorModel":{"Message":
+                        "Username or password is incorrect. Try again."}}"#
+                            .to_owned(),
+                    );
+                }
+                match second_factor {
+                    Some("two-step")
+                        if form.get("twoFactorToken").map(|t| &t[..]) != Some("123456") =>
+                    {
+                        (
+                            400,
+                            r#"{"error":"invalid_grant","TwoFactorProviders":["0","1"],
+                        "TwoFactorProviders2":{"0":null,"1":{"Email":"u***@example.com"}}}"#
+                                .to_owned(),
+                        )
+                    }
+                    Some("new-device")
+                        if form.get("newDeviceOtp").map(|t| &t[..]) != Some("654321") =>
+                    {
+                        (
+                            400,
+                            r#"{"error":"invalid_grant","error_description":
+                        "New device verification required"}"#
+                                .to_owned(),
+                        )
+                    }
+                    _ => (200, token(Some(&protected_key))),
+                }
+            }
+            "/identity/connect/revocation" => (200, String::new()),
+            "/api/sync" => (200, sync.clone()),
+            _ => (404, String::new()),
+        })
+    }
+
+    fn token(key: Option<&str>) -> String {
+        serde_json::json!({
+            "access_token": "access-token",
+            "refresh_token": REFRESH_TOKEN,
+            "Key": key,
+        })
+        .to_string()
+    }
+
+    fn state_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "bitwarden_rofi_test_{}_{}.session",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn session_error(err: &anyhow::Error) -> Option<&Error> {
+        err.downcast_ref::<Error>()
+    }
+
+    #[test]
+    fn unlock_and_reopen() {
+        let stand_in = server(None);
+        let state = state_file("unlock_and_reopen");
+
+        let session = Native::unlock(&stand_in.url, EMAIL, PASSWORD, None, &state).unwrap();
+        let items = session.list_items().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "example");
+        assert_eq!(session.read_field("1", "password").unwrap(), "hunter2");
+
+        // Neither the token nor the keys are readable from the state file.
+        let contents = fs::read_to_string(&state).unwrap();
+        assert!(contents.starts_with("2."));
+        assert!(!contents.contains(REFRESH_TOKEN));
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&state).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let session = Native::open(&stand_in.url, session.key(), &state).unwrap();
+        assert_eq!(session.status().unwrap().status, "unlocked");
+        assert_eq!(session.read_field("1", "username").unwrap(), "user");
+
+        // Another key does not open the state.
+        let other = base64::encode(SymmetricKey::generate().to_bytes());
+        assert!(Native::open(&stand_in.url, &other, &state).is_err());
+        fs::remove_file(&state).unwrap();
+    }
+
+    #[test]
+    fn invalid_password() {
+        let stand_in = server(None);
+        let state = state_file("invalid_password");
+
+        let err = Native::unlock(&stand_in.url, EMAIL, "wrong", None, &state)
+            .err()
+            .unwrap();
+        assert!(matches!(session_error(&err), Some(Error::InvalidPassword)));
+        assert!(!state.exists());
+    }
+
+    #[test]
+    fn two_step_login() {
+        let stand_in = server(Some("two-step"));
+        let state = state_file("two_step_login");
+
+        let err = Native::unlock(&stand_in.url, EMAIL, PASSWORD, None, &state)
+            .err()
+            .unwrap();
+        match session_error(&err) {
+            Some(Error::TwoStepRequired(providers)) => assert_eq!(providers, &vec![0, 1]),
+            _ => panic!("unexpected error: {}", err),
+        }
+
+        let second_factor = SecondFactor::TwoStep {
+            provider: 0,
+            code: "123456".to_owned(),
+        };
+        Native::unlock(&stand_in.url, EMAIL, PASSWORD, Some(&second_factor), &state).unwrap();
+        fs::remove_file(&state).unwrap();
+    }
+
+    #[test]
+    fn new_device_verification() {
+        let stand_in = server(Some("new-device"));
+        let state = state_file("new_device_verification");
+
+        let err = Native::unlock(&stand_in.url, EMAIL, PASSWORD, None, &state)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            session_error(&err),
+            Some(Error::NewDeviceVerification)
+        ));
+
+        let second_factor = SecondFactor::NewDevice("654321".to_owned());
+        Native::unlock(&stand_in.url, EMAIL, PASSWORD, Some(&second_factor), &state).unwrap();
+        fs::remove_file(&state).unwrap();
+    }
+
+    #[test]
+    fn lock_revokes_and_forgets() {
+        let stand_in = server(None);
+        let state = state_file("lock_revokes_and_forgets");
+
+        let session = Native::unlock(&stand_in.url, EMAIL, PASSWORD, None, &state).unwrap();
+        session.lock().unwrap();
+
+        assert!(!state.exists());
+        assert!(stand_in
+            .requests()
+            .contains(&"POST /identity/connect/revocation".to_owned()));
+        assert!(Native::open(&stand_in.url, session.key(), &state).is_err());
+    }
+}
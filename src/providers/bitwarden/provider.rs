@@ -1,58 +1,385 @@
-use super::session::{Error as SessionError, Session};
+use super::cli::Cli;
+use super::native::{Native, SecondFactor, DEFAULT_SERVER_URL, TWO_STEP_EMAIL};
+use super::send::NewSend;
+use super::serve::Serve;
+use super::session::{Error as SessionError, Item as SessionItem, Organization, Session};
 use crate::app::App;
-use crate::item::{Action, Field, Item};
+use crate::cache::Cache;
+use crate::clipboard;
+use crate::generator;
+use crate::generator::Options;
+use crate::item::{
+    Action, Attachment, Field, Item, ItemAction, Kind, NewLogin, OldPassword, PasswordHistory, Uri,
+    UriMatch,
+};
+use crate::notify;
 use crate::provider::Provider;
-use crate::rofi::RofiWindow;
-use anyhow::Result;
+use crate::providers::expand_home;
+use crate::report::Password;
+use crate::rofi::{RofiResponse, RofiWindow};
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
 use keyring::Keyring;
 use serde::Deserialize;
+use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::fs::DirBuilder;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
 
-#[serde(rename_all = "camelCase")]
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Config {
     cache: bool,
+    /// Offer to sync when the last sync is older than this many hours.
+    sync_threshold_hours: Option<i64>,
+    #[serde(default)]
+    backend: Backend,
+    /// Port `bw serve` listens on when using the serve backend, every
+    /// account needs its own. Defaults to one derived from the id.
+    port: Option<u16>,
+    /// Number of times the master password can be tried.
+    #[serde(default = "default_unlock_attempts")]
+    unlock_attempts: u32,
+    /// Data dir of `bw`, to keep several accounts logged in side by side.
+    /// Defaults to `bw_<id>` in the data dir of bitwarden_rofi.
+    appdata_dir: Option<String>,
+    /// Server and account, for the cli and serve backends only used to log
+    /// in. An API key is used instead of the password when the keyring has
+    /// `BW_CLIENTID_<id>` and `BW_CLIENTSECRET_<id>` entries.
+    server_url: Option<String>,
+    email: Option<String>,
+    /// Only show items of these organizations (by name or id), personal
+    /// items are always shown.
+    include_organizations: Option<Vec<String>>,
+    #[serde(default)]
+    exclude_organizations: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Cli,
+    Serve,
+    Native,
+}
+
+/// Two-step login methods that work with a typed code, by their number in
+/// TwoFactorProviderType.
+const TWO_STEP_METHODS: [(u8, &str); 3] = [
+    (0, "Authenticator app"),
+    (TWO_STEP_EMAIL, "Email"),
+    (3, "YubiKey OTP"),
+];
+
+/// Title of the action to create a Bitwarden Send.
+const SEND_ACTION: &str = "Create Send";
+
+/// A port for `bw serve` from 8087 on, different for most ids.
+fn default_port(id: &str) -> u16 {
+    let hash = id
+        .bytes()
+        .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+    8087 + (hash % 1000) as u16
+}
+
+fn default_unlock_attempts() -> u32 {
+    3
 }
 
 pub struct Bitwarden {
     id: String,
-    session: Option<Session>,
+    session: Option<Box<dyn Session>>,
     config: Config,
+    /// Items as listed last, so listing needs no unlocked vault.
+    cache: Option<Cache>,
+    /// Encrypted keys of the native backend, the keyring only holds the
+    /// key to them.
+    state_file: Option<PathBuf>,
+    /// Data dir of `bw`, only the global one when no other could be made.
+    appdata_dir: Option<PathBuf>,
 }
 
 impl Bitwarden {
     pub fn new(app: &App, id: &str, config: serde_json::Value) -> Box<dyn Provider> {
         let config: Config = serde_json::from_value(config).unwrap();
+        let cache = if config.cache {
+            match app.get_cache_file(&format!("bitwarden_{}.json", id)) {
+                Ok(path) => Some(Cache::try_load(&path)),
+                Err(err) => {
+                    eprintln!("{}: No cache file: {}", id, err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let state_file = match config.backend {
+            Backend::Native => match app.get_data_file(&format!("bitwarden_{}.session", id)) {
+                Ok(path) => Some(path),
+                Err(err) => {
+                    eprintln!("{}: No session file: {}", id, err);
+                    None
+                }
+            },
+            _ => None,
+        };
+        let appdata_dir = match (&config.appdata_dir, &config.backend) {
+            (Some(dir), _) => Some(expand_home(dir)),
+            (None, Backend::Native) => None,
+            (None, _) => match app.get_data_file(&format!("bw_{}", id)).and_then(|dir| {
+                DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+                Ok(dir)
+            }) {
+                Ok(dir) => Some(dir),
+                Err(err) => {
+                    eprintln!("{}: No data dir for bw, using the global one: {}", id, err);
+                    None
+                }
+            },
+        };
         Box::new(Self {
             config,
             id: id.to_owned(),
             session: None,
+            cache,
+            state_file,
+            appdata_dir,
         })
     }
 
-    fn get_session(&mut self) -> Result<&Session> {
+    fn get_session(&mut self) -> Result<&dyn Session> {
         if self.session.is_some() {
-            Ok(self.session.as_ref().unwrap())
+            Ok(self.session.as_deref().unwrap())
         } else {
             self.open_session()
         }
     }
 
-    fn open_session(&mut self) -> Result<&Session> {
-        let keyring = Keyring::new("bitwarden_rofi", "BW_SESSION");
+    fn open_backend(&self, key: &str) -> Result<Box<dyn Session>> {
+        let appdata_dir = self.appdata_dir();
+        Ok(match self.config.backend {
+            Backend::Cli => Box::new(Cli::open(key, appdata_dir.as_deref())),
+            Backend::Serve => Box::new(Serve::open(key, self.port(), appdata_dir.as_deref())?),
+            Backend::Native => Box::new(Native::open(self.server_url(), key, self.state_file()?)?),
+        })
+    }
+
+    fn unlock_backend(&self, password: &str) -> Result<Box<dyn Session>> {
+        let appdata_dir = self.appdata_dir();
+        Ok(match self.config.backend {
+            Backend::Cli => Box::new(Cli::unlock(password, appdata_dir.as_deref())?),
+            Backend::Serve => Box::new(Serve::unlock(
+                password,
+                self.port(),
+                appdata_dir.as_deref(),
+            )?),
+            Backend::Native => Box::new(self.unlock_native(password)?),
+        })
+    }
+
+    /// Unlock the native backend, asking for a second factor when the
+    /// server wants one.
+    fn unlock_native(&self, password: &str) -> Result<Native> {
+        let email = self.email()?;
+        let state_file = self.state_file()?;
+        let err = match Native::unlock(self.server_url(), &email, password, None, state_file) {
+            Ok(session) => return Ok(session),
+            Err(err) => err,
+        };
+
+        let second_factor = match err.downcast_ref::<SessionError>() {
+            Some(SessionError::TwoStepRequired(providers)) => {
+                let methods: Vec<String> = TWO_STEP_METHODS
+                    .iter()
+                    .filter(|(provider, _)| providers.contains(provider))
+                    .map(|(_, name)| name.to_string())
+                    .collect();
+                if methods.is_empty() {
+                    return Err(anyhow!(
+                        "None of the two-step login methods of the account are supported"
+                    ));
+                }
+                let method = RofiWindow::new("Two-step login")
+                    .add_args(vec!["-dmenu"])
+                    .lines(methods.len() as i32)
+                    .show(methods)?
+                    .entry()?;
+                let provider = match TWO_STEP_METHODS.iter().find(|(_, name)| *name == method) {
+                    Some((provider, _)) => *provider,
+                    None => return Err(err),
+                };
+                if provider == TWO_STEP_EMAIL {
+                    Native::send_email_code(self.server_url(), &email, password)?;
+                }
+                SecondFactor::TwoStep {
+                    provider,
+                    code: ask("Enter two-step login code", false)?,
+                }
+            }
+            Some(SessionError::NewDeviceVerification) => {
+                SecondFactor::NewDevice(ask("Enter the code emailed to verify this device", false)?)
+            }
+            _ => return Err(err),
+        };
+
+        Native::unlock(
+            self.server_url(),
+            &email,
+            password,
+            Some(&second_factor),
+            state_file,
+        )
+    }
+
+    fn state_file(&self) -> Result<&Path> {
+        self.state_file
+            .as_deref()
+            .ok_or_else(|| anyhow!("{}: No session file", self.id))
+    }
+
+    /// Ask for the master password until it unlocks the vault, or until
+    /// there are no attempts left.
+    fn unlock_with_retries(&self) -> Result<Box<dyn Session>> {
+        let attempts = self.config.unlock_attempts.max(1);
+        let mut attempt = 1;
+        let mut message: Option<String> = None;
+
+        loop {
+            let mut window = RofiWindow::new("Enter master password")
+                .add_args(vec!["-dmenu"])
+                .password(true)
+                .lines(0);
+            if let Some(message) = &message {
+                window = window.message(message);
+            }
+            let password = window.show(vec![])?.entry()?;
+
+            let err = match self.unlock_backend(&password) {
+                Ok(session) => return Ok(session),
+                Err(err) => err,
+            };
+            match err.downcast_ref::<SessionError>() {
+                Some(SessionError::InvalidPassword) | Some(SessionError::UnlockFailed) => {}
+                _ => return Err(err),
+            }
+
+            if attempt >= attempts {
+                notify::send(
+                    &format!("{}: vault not unlocked", self.id),
+                    &format!("{} after {} attempts", err, attempts),
+                );
+                return Err(err);
+            }
+
+            attempt += 1;
+            message = Some(format!("{}, try again ({}/{})", err, attempt, attempts));
+        }
+    }
+
+    /// Log `bw` in when it is not, for the cli and serve backends. Gives the
+    /// session when logging in also unlocked the vault.
+    fn login(&self) -> Result<Option<Box<dyn Session>>> {
+        if let Backend::Native = self.config.backend {
+            return Ok(None);
+        }
+
+        let appdata_dir = self.appdata_dir();
+        let dir = appdata_dir.as_deref();
+        if Cli::status_of(dir)?.status != "unauthenticated" {
+            return Ok(None);
+        }
+
+        if let Some(url) = &self.config.server_url {
+            Cli::config_server(url, dir)?;
+        }
+
+        let client_id = format!("BW_CLIENTID_{}", self.id);
+        let client_secret = format!("BW_CLIENTSECRET_{}", self.id);
+        if let (Ok(client_id), Ok(client_secret)) = (
+            Keyring::new("bitwarden_rofi", &client_id).get_password(),
+            Keyring::new("bitwarden_rofi", &client_secret).get_password(),
+        ) {
+            Cli::login_apikey(&client_id, &client_secret, dir)?;
+            return Ok(None);
+        }
+
+        let email = self.email()?;
+        let password = ask("Enter master password", true)?;
+
+        let methods: Vec<String> = std::iter::once("None")
+            .chain(TWO_STEP_METHODS.iter().map(|(_, name)| *name))
+            .map(str::to_owned)
+            .collect();
+        let method = RofiWindow::new("Two-step login")
+            .add_args(vec!["-dmenu"])
+            .lines(methods.len() as i32)
+            .show(methods)?
+            .entry()?;
+        let method = TWO_STEP_METHODS
+            .iter()
+            .find(|(_, name)| *name == method)
+            .map(|(provider, _)| *provider);
+
+        let code = match method {
+            Some(method) => {
+                if method == TWO_STEP_EMAIL {
+                    // Without a code this only has the server send one.
+                    Cli::login(&email, &password, Some(method), None, dir).ok();
+                }
+                Some(ask("Enter two-step login code", false)?)
+            }
+            None => None,
+        };
+
+        let cli = Cli::login(&email, &password, method, code.as_deref(), dir)?;
+        Ok(Some(self.open_backend(cli.key())?))
+    }
+
+    fn email(&self) -> Result<String> {
+        match &self.config.email {
+            Some(email) => Ok(email.to_owned()),
+            None => ask("Enter email", false),
+        }
+    }
+
+    fn server_url(&self) -> &str {
+        self.config
+            .server_url
+            .as_deref()
+            .unwrap_or(DEFAULT_SERVER_URL)
+    }
+
+    fn appdata_dir(&self) -> Option<PathBuf> {
+        self.appdata_dir.clone()
+    }
+
+    fn port(&self) -> u16 {
+        self.config.port.unwrap_or_else(|| default_port(&self.id))
+    }
+
+    /// Keyring user of the session key, one per configured account.
+    fn keyring_user(&self) -> String {
+        format!("BW_SESSION_{}", self.id)
+    }
+
+    fn open_session(&mut self) -> Result<&dyn Session> {
+        let user = self.keyring_user();
+        let keyring = Keyring::new("bitwarden_rofi", &user);
 
         let session = match keyring.get_password() {
             Ok(key) => {
-                let session = Session::open(&key);
+                let session = self.open_backend(&key)?;
                 match session.is_unlocked() {
                     Ok(true) => Some(session),
                     Ok(false) => {
-                        eprintln!("bitwarden: Session key is not valid");
+                        eprintln!("{}: Session key is not valid", self.id);
                         None
                     }
                     Err(err) => match err.downcast_ref::<SessionError>() {
                         Some(SessionError::FailedToDecrypt) => {
-                            eprintln!("bitwarden: Failed to decrypt");
+                            eprintln!("{}: Failed to decrypt", self.id);
                             None
                         }
                         _ => return Err(err),
@@ -65,44 +392,70 @@ impl Bitwarden {
         session
             .map(Ok)
             .unwrap_or_else(|| {
-                let password = RofiWindow::new("Enter master password")
-                    .add_args(vec!["-dmenu"])
-                    .password(true)
-                    .lines(0)
-                    .show(vec![])?
-                    .entry()?;
+                let session = match self.login()? {
+                    Some(session) => session,
+                    None => self.unlock_with_retries()?,
+                };
 
-                let session = Session::unlock(&password)?;
-
-                keyring.set_password(&session.key).unwrap_or_else(|err| {
-                    eprintln!("bitwarden: Failed to put session key in keyring: {}", err);
+                keyring.set_password(session.key()).unwrap_or_else(|err| {
+                    eprintln!("{}: Failed to put session key in keyring: {}", self.id, err);
                 });
 
                 Ok(session)
             })
             .map(move |session| {
                 self.session = Some(session);
-                self.session.as_ref().unwrap()
+                self.session.as_deref().unwrap()
             })
     }
 
-    fn lock(&mut self) -> Result<()> {
-        // TODO: this can open a session if it wasn't...
-        self.get_session()?.lock()?;
-        let keyring = Keyring::new("bitwarden_rofi", "BW_SESSION");
-        keyring.delete_password().unwrap_or_else(|err| {
-            eprintln!("Deleting entry from keyring failed: {}", err);
-        });
+    /// Edit the JSON of an item, and update the cache with the result.
+    fn edit_raw_item<F>(&mut self, item: &Item, edit: F) -> Result<()>
+    where
+        F: FnOnce(&mut Value) -> Result<()>,
+    {
+        let session = self.get_session()?;
+        let mut raw = session.get_raw_item(&item.id)?;
+        edit(&mut raw)?;
+        let edited = session.edit_item(&item.id, &raw)?;
+        self.update_cache(&item.id, vec![edited])
+    }
+
+    fn update_cache(&mut self, id: &str, session_items: Vec<SessionItem>) -> Result<()> {
+        if self.cache.is_none() {
+            return Ok(());
+        }
+        let items = self.convert_items(session_items)?;
+        if let Some(cache) = &mut self.cache {
+            cache.update(id, items);
+        }
         Ok(())
     }
 
-    fn sync(&mut self) -> Result<()> {
-        self.get_session()?.sync()
+    /// Id of the folder with a name, which is created when there is none.
+    fn folder_id(&mut self, name: &str) -> Result<String> {
+        let session = self.get_session()?;
+        let folder = session
+            .list_folders()?
+            .into_iter()
+            .find(|f| f.id.is_some() && f.name == name);
+        let folder = match folder {
+            Some(folder) => folder,
+            None => session.create_folder(name)?,
+        };
+        folder
+            .id
+            .ok_or_else(|| anyhow!("Folder {} has no id", name))
     }
-}
 
-impl Provider for Bitwarden {
-    fn list_items(&mut self) -> Result<Vec<Item>> {
+    fn fetch_items(&mut self) -> Result<Vec<Item>> {
+        self.check_last_sync()?;
+        let session_items = self.get_session()?.list_items()?;
+        self.convert_items(session_items)
+    }
+
+    /// Items as shown in the menu, from the items of the session.
+    fn convert_items(&mut self, session_items: Vec<SessionItem>) -> Result<Vec<Item>> {
         let mut folders = HashMap::new();
 
         let session = self.get_session()?;
@@ -113,18 +466,51 @@ impl Provider for Bitwarden {
 
         let mut items: Vec<Item> = vec![];
 
-        for i in session.list_items()?.into_iter() {
-            let mut path = match folders.get(&i.folder_id) {
-                None => vec![],
-                _ if i.folder_id.is_none() => vec![],
-                Some(folder) => folder.name.split("/").map(|s| s.to_string()).collect(),
-            };
+        // Saves two calls to bw for those without organizations.
+        let mut organizations = HashMap::new();
+        let mut collections = HashMap::new();
+        if session_items.iter().any(|i| i.organization_id.is_some()) {
+            for o in session.list_organizations()?.into_iter() {
+                organizations.insert(o.id.clone(), o);
+            }
+            for c in session.list_collections()?.into_iter() {
+                collections.insert(c.id.clone(), c);
+            }
+        }
 
-            path.push(i.name);
-            let title = path.join("/");
+        for i in session_items.into_iter() {
+            // Shared items are shown under OrgName/Collection/…, once for
+            // every collection they are in.
+            let paths: Vec<Vec<String>> = match &i.organization_id {
+                Some(org_id) => {
+                    let org = match organizations.get(org_id) {
+                        Some(org) if self.show_organization(org) => org,
+                        _ => continue,
+                    };
+                    let mut paths: Vec<Vec<String>> = i
+                        .collection_ids
+                        .iter()
+                        .filter_map(|id| collections.get(id))
+                        .map(|c| {
+                            let mut path = vec![org.name.clone()];
+                            path.extend(c.name.split('/').map(|s| s.to_string()));
+                            path
+                        })
+                        .collect();
+                    if paths.is_empty() {
+                        paths.push(vec![org.name.clone()]);
+                    }
+                    paths
+                }
+                None => vec![match folders.get(&i.folder_id) {
+                    None => vec![],
+                    _ if i.folder_id.is_none() => vec![],
+                    Some(folder) => folder.name.split('/').map(|s| s.to_string()).collect(),
+                }],
+            };
 
             let mut fields = vec![];
-            if let Some(login) = i.login {
+            if let Some(login) = &i.login {
                 if login.username.is_some() {
                     fields.push(Field::Username);
                 }
@@ -135,33 +521,497 @@ impl Provider for Bitwarden {
                     fields.push(Field::Totp);
                 }
             }
+            if i.notes.as_ref().is_some_and(|notes| !notes.is_empty()) {
+                fields.push(Field::Notes);
+            }
+            for extra in i.extra_fields() {
+                fields.push(if extra.secret {
+                    Field::Hidden(extra.name.to_owned())
+                } else {
+                    Field::Other(extra.name.to_owned())
+                });
+            }
+            for f in i.fields.iter() {
+                if let Some(name) = &f.name {
+                    fields.push(if f.is_secret() {
+                        Field::Hidden(name.to_owned())
+                    } else {
+                        Field::Other(name.to_owned())
+                    });
+                }
+            }
 
-            let item = Item {
-                id: i.id,
-                title,
-                fields,
+            let kind = match i.type_ {
+                2 => Kind::Note,
+                3 => Kind::Card,
+                4 => Kind::Identity,
+                _ => Kind::Login,
             };
 
-            items.push(item);
+            let uris: Vec<Uri> = i
+                .login
+                .iter()
+                .flat_map(|login| login.uris.iter())
+                .map(|u| Uri {
+                    uri: u.uri.clone(),
+                    match_: match u.match_ {
+                        Some(1) => UriMatch::Host,
+                        Some(2) => UriMatch::StartsWith,
+                        Some(3) => UriMatch::Exact,
+                        Some(4) => UriMatch::RegularExpression,
+                        Some(5) => UriMatch::Never,
+                        _ => UriMatch::Domain,
+                    },
+                })
+                .collect();
+
+            let attachments: Vec<Attachment> = i
+                .attachments
+                .iter()
+                .flatten()
+                .map(|a| Attachment {
+                    id: a.id.clone(),
+                    file_name: a.file_name.clone(),
+                    size: a.size_name.clone(),
+                })
+                .collect();
+
+            for mut path in paths.into_iter() {
+                path.push(i.name.clone());
+
+                let item = Item {
+                    id: i.id.clone(),
+                    title: path.join("/"),
+                    fields: fields.clone(),
+                    kind,
+                    uris: uris.clone(),
+                    favorite: i.favorite,
+                    attachments: attachments.clone(),
+                };
+
+                items.push(item);
+            }
         }
 
         Ok(items)
     }
 
-    fn read_field(&mut self, item: &Item, field: &Field) -> Result<String> {
-        let field_name = match field {
-            Field::Username => "username",
-            Field::Password => "password",
-            Field::Totp => "totp",
-            Field::Other(name) => &name,
+    fn show_organization(&self, org: &Organization) -> bool {
+        let listed = |names: &Vec<String>| names.iter().any(|n| *n == org.name || *n == org.id);
+        let included = match &self.config.include_organizations {
+            Some(names) => listed(names),
+            None => true,
+        };
+        included && !listed(&self.config.exclude_organizations)
+    }
+
+    fn check_last_sync(&mut self) -> Result<()> {
+        let threshold = match self.config.sync_threshold_hours {
+            Some(hours) => Duration::hours(hours),
+            None => return Ok(()),
         };
+
+        let session = self.get_session()?;
+        let message = match session.status()?.last_sync {
+            Some(last_sync) if Utc::now() - last_sync < threshold => return Ok(()),
+            Some(last_sync) => format!(
+                "Vault was last synced {}",
+                format_age(Utc::now() - last_sync)
+            ),
+            None => "Vault was never synced".to_owned(),
+        };
+
+        let res = RofiWindow::new("Sync now?")
+            .message(&message)
+            .add_args(vec!["-dmenu"])
+            .lines(2)
+            .show(vec!["Yes".to_owned(), "No".to_owned()])?;
+
+        if let RofiResponse::Entry(ref s) = res {
+            if s == "Yes" {
+                session.sync()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn ask(prompt: &str, password: bool) -> Result<String> {
+    RofiWindow::new(prompt)
+        .add_args(vec!["-dmenu"])
+        .password(password)
+        .lines(0)
+        .show(vec![])?
+        .entry()
+}
+
+fn format_age(age: Duration) -> String {
+    if age < Duration::minutes(1) {
+        "just now".to_owned()
+    } else if age < Duration::hours(1) {
+        format!("{}m ago", age.num_minutes())
+    } else if age < Duration::days(1) {
+        format!("{}h ago", age.num_hours())
+    } else {
+        format!("{}d ago", age.num_days())
+    }
+}
+
+impl Provider for Bitwarden {
+    fn list_items(&mut self) -> Result<Vec<Item>> {
+        // Without a threshold the cache is only refreshed by syncing.
+        let max_age = self
+            .config
+            .sync_threshold_hours
+            .map_or(Duration::max_value(), Duration::hours);
+        if let Some(cache) = &self.cache {
+            if cache.is_fresh(max_age) {
+                return Ok(cache.items().clone());
+            }
+        }
+
+        let items = self.fetch_items()?;
+        if let Some(cache) = &mut self.cache {
+            cache.replace(items.clone());
+        }
+        Ok(items)
+    }
+
+    /// All passwords from a single listing instead of one call per item.
+    /// Shared items are listed once, under their first collection.
+    fn list_passwords(&mut self) -> Result<Vec<Password>> {
+        let mut items: HashMap<String, Item> = HashMap::new();
+        for item in self.list_items()? {
+            items.entry(item.id.clone()).or_insert(item);
+        }
+
+        let session_items = self.get_session()?.list_items()?;
+        Ok(session_items
+            .into_iter()
+            .filter_map(|i| {
+                let item = items.remove(&i.id)?;
+                let login = i.login?;
+                let password = login.password.filter(|p| !p.is_empty())?;
+                Some(Password {
+                    item,
+                    password,
+                    // Only set once the password is changed.
+                    revision_date: login.password_revision_date.or(i.creation_date),
+                })
+            })
+            .collect())
+    }
+
+    fn read_field(&mut self, item: &Item, field: &Field) -> Result<String> {
         let session = self.get_session()?;
-        session.read_field(&item.id, field_name)
+        match field {
+            // The code is generated locally from the secret.
+            Field::Totp => session
+                .get_item(&item.id)?
+                .login
+                .and_then(|login| login.totp)
+                .ok_or_else(|| anyhow!("{} has no TOTP secret", item.title)),
+            // `bw get` does not know about custom fields.
+            Field::Other(name) | Field::Hidden(name) => {
+                let i = session.get_item(&item.id)?;
+                i.custom_field(name)
+                    .or_else(|| {
+                        i.extra_fields()
+                            .into_iter()
+                            .find(|extra| extra.name == name)
+                            .map(|extra| extra.value)
+                    })
+                    .ok_or_else(|| anyhow!("{} has no {} field", item.title, name))
+            }
+            _ => session.read_field(&item.id, field.name()),
+        }
     }
 
     fn list_actions(&mut self) -> Result<Vec<Action>> {
-        Ok(vec![])
+        let mut actions = vec![Action {
+            title: generator::ACTION.to_owned(),
+            shortcut: "Alt+g".to_owned(),
+        }];
+        if self.is_writable() {
+            actions.push(Action {
+                title: SEND_ACTION.to_owned(),
+                shortcut: "Alt+s".to_owned(),
+            });
+        }
+        Ok(actions)
+    }
+
+    fn do_action(&mut self, action: &Action) -> Result<()> {
+        if action.title == generator::ACTION {
+            if let Some(options) = generator::ask()? {
+                let password = self.generate_password(&options)?;
+                generator::deliver(&password)?;
+            }
+        } else if action.title == SEND_ACTION {
+            if let Some(send) = NewSend::ask()? {
+                let created = self
+                    .get_session()?
+                    .create_send(&send.to_json(), send.file().map(|f| f.as_path()))?;
+                let url = created["accessUrl"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("The created Send has no URL"))?;
+                clipboard::copy(url)?;
+                eprintln!("{}: Copied the URL of Send {}", self.id, send.name);
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_password(&mut self, options: &Options) -> Result<String> {
+        match self.config.backend {
+            Backend::Native => generator::generate(options, None),
+            _ => Cli::generate(options, self.appdata_dir().as_deref()),
+        }
+    }
+
+    fn is_writable(&self) -> bool {
+        // The native backend cannot encrypt.
+        !matches!(self.config.backend, Backend::Native)
+    }
+
+    fn list_folders(&mut self) -> Result<Vec<String>> {
+        let folders = self.get_session()?.list_folders()?;
+        Ok(folders
+            .into_iter()
+            .filter(|f| f.id.is_some())
+            .map(|f| f.name)
+            .collect())
     }
 
-    fn do_action(&mut self, action: &Action) {}
+    fn create_item(&mut self, login: &NewLogin) -> Result<Item> {
+        let folder_id = match &login.folder {
+            Some(name) => Some(self.folder_id(name)?),
+            None => None,
+        };
+        let uris: Vec<Value> = login
+            .uri
+            .iter()
+            .map(|uri| json!({ "match": null, "uri": uri }))
+            .collect();
+
+        // As `bw get template item` gives.
+        let item = json!({
+            "organizationId": null,
+            "collectionIds": null,
+            "folderId": folder_id,
+            "type": 1,
+            "name": login.name,
+            "notes": null,
+            "favorite": false,
+            "fields": [],
+            "login": {
+                "uris": uris,
+                "username": login.username,
+                "password": login.password,
+                "totp": null,
+            },
+            "reprompt": 0,
+        });
+        let created = self.get_session()?.create_item(&item)?;
+        let id = created.id.clone();
+        self.update_cache(&id, vec![created])?;
+
+        let mut fields = vec![];
+        if login.username.is_some() {
+            fields.push(Field::Username);
+        }
+        fields.push(Field::Password);
+
+        Ok(Item {
+            id,
+            title: match &login.folder {
+                Some(folder) => format!("{}/{}", folder, login.name),
+                None => login.name.clone(),
+            },
+            fields,
+            kind: Kind::Login,
+            uris: login
+                .uri
+                .iter()
+                .map(|uri| Uri {
+                    uri: uri.clone(),
+                    match_: UriMatch::Domain,
+                })
+                .collect(),
+            favorite: false,
+            attachments: vec![],
+        })
+    }
+
+    fn has_password_history(&self) -> bool {
+        true
+    }
+
+    fn password_history(&mut self, item: &Item) -> Result<PasswordHistory> {
+        let i = self.get_session()?.get_item(&item.id)?;
+        let mut passwords: Vec<OldPassword> = i
+            .password_history
+            .into_iter()
+            .flatten()
+            .map(|h| OldPassword {
+                password: h.password,
+                last_used: h.last_used_date,
+            })
+            .collect();
+        passwords.sort_by_key(|p| std::cmp::Reverse(p.last_used));
+
+        Ok(PasswordHistory {
+            revision_date: i.login.and_then(|l| l.password_revision_date),
+            passwords,
+        })
+    }
+
+    fn save_attachment(&mut self, item: &Item, attachment: &Attachment, path: &Path) -> Result<()> {
+        self.get_session()?
+            .get_attachment(&item.id, &attachment.id, path)
+    }
+
+    fn update_field(&mut self, item: &Item, field: &Field, value: &str) -> Result<()> {
+        self.edit_raw_item(item, |raw| {
+            match field {
+                Field::Username => raw["login"]["username"] = Value::from(value),
+                Field::Password => raw["login"]["password"] = Value::from(value),
+                Field::Totp => raw["login"]["totp"] = Value::from(value),
+                Field::Notes => raw["notes"] = Value::from(value),
+                Field::Other(name) | Field::Hidden(name) => {
+                    let custom = raw["fields"]
+                        .as_array_mut()
+                        .and_then(|fields| fields.iter_mut().find(|f| f["name"] == name.as_str()));
+                    match custom {
+                        Some(custom) => custom["value"] = Value::from(value),
+                        // Card and identity values, and linked fields.
+                        None => return Err(anyhow!("{} cannot be edited from here", name)),
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn item_actions(&self) -> Vec<ItemAction> {
+        if self.is_writable() {
+            vec![
+                ItemAction::Move,
+                ItemAction::Delete,
+                ItemAction::ToggleFavorite,
+            ]
+        } else {
+            vec![]
+        }
+    }
+
+    fn move_item(&mut self, item: &Item, folder: Option<&str>) -> Result<()> {
+        let folder_id = match folder {
+            Some(name) => Some(self.folder_id(name)?),
+            None => None,
+        };
+        self.edit_raw_item(item, |raw| {
+            raw["folderId"] = Value::from(folder_id);
+            Ok(())
+        })
+    }
+
+    fn delete_item(&mut self, item: &Item) -> Result<()> {
+        self.get_session()?.delete_item(&item.id)?;
+        self.update_cache(&item.id, vec![])
+    }
+
+    fn set_favorite(&mut self, item: &Item, favorite: bool) -> Result<()> {
+        self.edit_raw_item(item, |raw| {
+            raw["favorite"] = Value::from(favorite);
+            Ok(())
+        })
+    }
+
+    fn lock(&mut self) -> Result<()> {
+        // Never use get_session here: that would ask for the master password
+        // only to lock the vault again.
+        let user = self.keyring_user();
+        let keyring = Keyring::new("bitwarden_rofi", &user);
+        let session = self.session.take().or_else(|| {
+            let key = keyring.get_password().ok()?;
+            self.open_backend(&key)
+                .map_err(|err| eprintln!("{}: Could not open session: {}", self.id, err))
+                .ok()
+        });
+
+        // Gone from the keyring even when locking fails below.
+        keyring.delete_password().unwrap_or_else(|err| {
+            eprintln!("Deleting entry from keyring failed: {}", err);
+        });
+
+        if let Some(session) = session {
+            match session.is_unlocked() {
+                Ok(true) => session.lock()?,
+                Ok(false) => eprintln!("{}: Vault is already locked", self.id),
+                Err(err) => eprintln!("{}: Could not get status: {}", self.id, err),
+            }
+        }
+        Ok(())
+    }
+
+    fn unlock(&mut self) -> Result<()> {
+        self.get_session()?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.get_session()?.sync()?;
+        if self.cache.is_some() {
+            let items = self.fetch_items()?;
+            if let Some(cache) = &mut self.cache {
+                cache.replace(items);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_locked(&mut self) -> Result<bool> {
+        match &self.session {
+            Some(session) => Ok(!session.is_unlocked()?),
+            None => Ok(true),
+        }
+    }
+
+    fn status(&mut self) -> Result<Option<String>> {
+        let status = match &self.session {
+            Some(session) => session.status()?,
+            // Opening the session just for this would cost as much as not
+            // using the cache, so show the account from the last time.
+            None => {
+                let cached = self.cache.as_ref().and_then(|cache| {
+                    let listed = cache.listed()?;
+                    Some(format!(
+                        "{} | listed {}",
+                        cache.status()?,
+                        format_age(Utc::now() - listed)
+                    ))
+                });
+                return Ok(Some(cached.unwrap_or_else(|| "locked".to_owned())));
+            }
+        };
+
+        let mut parts = vec![];
+        if let Some(email) = status.user_email {
+            parts.push(email);
+        }
+        if let Some(server_url) = status.server_url {
+            parts.push(server_url);
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.set_status(&parts.join(" | "));
+        }
+        parts.push(match status.last_sync {
+            Some(last_sync) => format!("last synced {}", format_age(Utc::now() - last_sync)),
+            None => "never synced".to_owned(),
+        });
+
+        Ok(Some(parts.join(" | ")))
+    }
 }
@@ -0,0 +1,125 @@
+//! Bitwarden Send, to share text or a file through a link that expires.
+
+use crate::clipboard;
+use crate::providers::expand_home;
+use crate::rofi::{ask, choose};
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+pub enum Content {
+    Text(String),
+    File(PathBuf),
+}
+
+pub struct NewSend {
+    pub name: String,
+    pub content: Content,
+    pub expires_in: Duration,
+    pub max_access_count: Option<u32>,
+    pub password: Option<String>,
+}
+
+const EXPIRY: &[(&str, i64)] = &[
+    ("1 hour", 1),
+    ("1 day", 24),
+    ("2 days", 2 * 24),
+    ("7 days", 7 * 24),
+    ("30 days", 30 * 24),
+];
+
+impl NewSend {
+    /// Ask what to send and how. Gives `None` when cancelled.
+    pub fn ask() -> Result<Option<NewSend>> {
+        let sources = vec!["Clipboard text".to_owned(), "File".to_owned()];
+        let content = match choose("Send", sources)? {
+            Some(s) if s == "Clipboard text" => Content::Text(clipboard::paste()?),
+            Some(s) if s == "File" => match ask("File", None, false)? {
+                Some(path) if !path.is_empty() => {
+                    let path = expand_home(&path);
+                    if !path.is_file() {
+                        return Err(anyhow!("{} is not a file", path.display()));
+                    }
+                    Content::File(path)
+                }
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let name = match &content {
+            Content::Text(_) => "Text".to_owned(),
+            Content::File(path) => path
+                .file_name()
+                .map_or_else(|| "File".to_owned(), |n| n.to_string_lossy().into_owned()),
+        };
+
+        let expiries = EXPIRY.iter().map(|(name, _)| name.to_string()).collect();
+        let expires_in = match choose("Expires in", expiries)? {
+            Some(s) => match EXPIRY.iter().find(|(name, _)| *name == s) {
+                Some((_, hours)) => Duration::hours(*hours),
+                None => return Err(anyhow!("Unknown expiry {}", s)),
+            },
+            None => return Ok(None),
+        };
+
+        let max_access_count = match ask(
+            "Maximum access count",
+            Some("Leave empty for no limit"),
+            false,
+        )? {
+            Some(s) if s.trim().is_empty() => None,
+            Some(s) => Some(
+                s.trim()
+                    .parse()
+                    .map_err(|_| anyhow!("{} is not a number", s))?,
+            ),
+            None => return Ok(None),
+        };
+
+        let password = match ask("Password", Some("Leave empty for none"), true)? {
+            Some(s) if s.is_empty() => None,
+            Some(s) => Some(s),
+            None => return Ok(None),
+        };
+
+        Ok(Some(NewSend {
+            name,
+            content,
+            expires_in,
+            max_access_count,
+            password,
+        }))
+    }
+
+    /// The send as `bw send template` gives it. For files the file itself
+    /// is passed separately.
+    pub fn to_json(&self) -> Value {
+        let expires = (Utc::now() + self.expires_in).to_rfc3339();
+        let (type_, text, file) = match &self.content {
+            Content::Text(text) => (0, json!({ "text": text, "hidden": false }), Value::Null),
+            Content::File(_) => (1, Value::Null, json!({ "fileName": self.name })),
+        };
+
+        json!({
+            "name": self.name,
+            "notes": null,
+            "type": type_,
+            "text": text,
+            "file": file,
+            "maxAccessCount": self.max_access_count,
+            "deletionDate": expires,
+            "expirationDate": expires,
+            "password": self.password,
+            "disabled": false,
+            "hideEmail": false,
+        })
+    }
+
+    pub fn file(&self) -> Option<&PathBuf> {
+        match &self.content {
+            Content::File(path) => Some(path),
+            Content::Text(_) => None,
+        }
+    }
+}
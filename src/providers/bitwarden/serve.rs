@@ -0,0 +1,341 @@
+use super::cli::{bw, spawn_error};
+use super::session::{Collection, Error, Folder, Item, Organization, Session, Status};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fs::File;
+use std::io;
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Child, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for `bw serve` to start listening.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Session that talks to a `bw serve` process, started once, over its Vault
+/// Management REST API. This saves spawning a `bw` process for every call.
+pub struct Serve {
+    key: String,
+    base_url: String,
+    process: Child,
+}
+
+#[derive(Deserialize, Debug)]
+struct Response<T> {
+    success: bool,
+    message: Option<String>,
+    data: Option<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct List<T> {
+    data: Vec<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Text {
+    data: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Template<T> {
+    template: T,
+}
+
+#[derive(Deserialize, Debug)]
+struct Message {
+    raw: Option<String>,
+}
+
+impl Serve {
+    pub fn open(key: &str, port: u16, appdata_dir: Option<&Path>) -> Result<Serve> {
+        Serve::start(Some(key), port, appdata_dir)
+    }
+
+    pub fn unlock(password: &str, port: u16, appdata_dir: Option<&Path>) -> Result<Serve> {
+        let mut serve = Serve::start(None, port, appdata_dir)?;
+
+        let body = serde_json::json!({ "password": password });
+        let message: Message = serve.call("POST", "/unlock", Some(body))?;
+
+        serve.key = message
+            .raw
+            .ok_or_else(|| anyhow::Error::from(Error::UnlockFailed))?;
+
+        Ok(serve)
+    }
+
+    fn start(key: Option<&str>, port: u16, appdata_dir: Option<&Path>) -> Result<Serve> {
+        // Otherwise the requests would go to whatever listens there, which
+        // may well be the vault of another account.
+        if TcpStream::connect(("localhost", port)).is_ok() {
+            return Err(Error::PortInUse(port).into());
+        }
+
+        let mut command = bw(appdata_dir);
+        command
+            .args(["serve", "--hostname", "localhost", "--port"])
+            .arg(port.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(key) = key {
+            command.env("BW_SESSION", key);
+        }
+
+        let mut serve = Serve {
+            key: key.unwrap_or_default().to_owned(),
+            base_url: format!("http://localhost:{}", port),
+            process: command.spawn().map_err(spawn_error)?,
+        };
+
+        let started = Instant::now();
+        while TcpStream::connect(("localhost", port)).is_err() {
+            // Eg. when something else took the port in the meantime.
+            if let Some(status) = serve.process.try_wait()? {
+                return Err(anyhow::Error::from(Error::UnexpectedResponse(format!(
+                    "bw serve exited with {} before listening",
+                    status
+                ))));
+            }
+            if started.elapsed() > STARTUP_TIMEOUT {
+                return Err(anyhow::Error::from(Error::UnexpectedResponse(
+                    "bw serve did not start listening".to_owned(),
+                )));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        Ok(serve)
+    }
+
+    fn call<T>(&self, method: &str, path: &str, body: Option<serde_json::Value>) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let request = ureq::request(method, &format!("{}{}", self.base_url, path));
+        let result = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+
+        // bw serve answers failures with a 4xx status and a regular body.
+        let response: Response<T> = match result {
+            Ok(response) => response.into_json()?,
+            Err(ureq::Error::Status(_, response)) => response.into_json()?,
+            Err(err) => return Err(err.into()),
+        };
+
+        match response {
+            Response {
+                success: true,
+                data: Some(data),
+                ..
+            } => Ok(data),
+            // Eg. deleting answers without data.
+            Response {
+                success: true,
+                data: None,
+                ..
+            } => Ok(serde_json::from_value(serde_json::Value::Null)?),
+            Response { message, .. } => Err(anyhow::Error::from(Error::from_message(
+                &message.unwrap_or_default(),
+            ))),
+        }
+    }
+}
+
+impl Session for Serve {
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn lock(&self) -> Result<()> {
+        self.call::<serde_json::Value>("POST", "/lock", None)?;
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.call::<serde_json::Value>("POST", "/sync", None)?;
+        Ok(())
+    }
+
+    fn status(&self) -> Result<Status> {
+        let status: Template<Status> = self.call("GET", "/status", None)?;
+        Ok(status.template)
+    }
+
+    fn list_folders(&self) -> Result<Vec<Folder>> {
+        let list: List<Folder> = self.call("GET", "/list/object/folders", None)?;
+        Ok(list.data)
+    }
+
+    fn list_organizations(&self) -> Result<Vec<Organization>> {
+        let list: List<Organization> = self.call("GET", "/list/object/organizations", None)?;
+        Ok(list.data)
+    }
+
+    fn list_collections(&self) -> Result<Vec<Collection>> {
+        let list: List<Collection> = self.call("GET", "/list/object/collections", None)?;
+        Ok(list.data)
+    }
+
+    fn list_items(&self) -> Result<Vec<Item>> {
+        let list: List<Item> = self.call("GET", "/list/object/items", None)?;
+        Ok(list.data)
+    }
+
+    fn get_item(&self, id: &str) -> Result<Item> {
+        self.call("GET", &format!("/object/item/{}", id), None)
+    }
+
+    fn read_field(&self, id: &str, field: &str) -> Result<String> {
+        let text: Text = self.call("GET", &format!("/object/{}/{}", field, id), None)?;
+        Ok(text.data)
+    }
+
+    fn get_raw_item(&self, id: &str) -> Result<serde_json::Value> {
+        self.call("GET", &format!("/object/item/{}", id), None)
+    }
+
+    fn create_item(&self, item: &serde_json::Value) -> Result<Item> {
+        self.call("POST", "/object/item", Some(item.clone()))
+    }
+
+    fn edit_item(&self, id: &str, item: &serde_json::Value) -> Result<Item> {
+        self.call("PUT", &format!("/object/item/{}", id), Some(item.clone()))
+    }
+
+    fn create_folder(&self, name: &str) -> Result<Folder> {
+        let folder = serde_json::json!({ "name": name });
+        self.call("POST", "/object/folder", Some(folder))
+    }
+
+    fn get_attachment(&self, item_id: &str, id: &str, output: &Path) -> Result<()> {
+        // The file itself is the answer, not JSON.
+        let response = ureq::get(&format!("{}/object/attachment/{}", self.base_url, id))
+            .query("itemid", item_id)
+            .call()
+            .map_err(|err| match err {
+                ureq::Error::Status(_, response) => {
+                    let body = response.into_string().unwrap_or_default();
+                    anyhow::Error::from(Error::from_message(&body))
+                }
+                err => err.into(),
+            })?;
+
+        let mut file = File::create(output)?;
+        io::copy(&mut response.into_reader(), &mut file)?;
+        Ok(())
+    }
+
+    fn create_send(
+        &self,
+        send: &serde_json::Value,
+        file: Option<&Path>,
+    ) -> Result<serde_json::Value> {
+        if file.is_some() {
+            return Err(Error::Unsupported("Sending a file").into());
+        }
+        self.call("POST", "/object/send", Some(send.clone()))
+    }
+
+    fn delete_item(&self, id: &str) -> Result<()> {
+        self.call::<serde_json::Value>("DELETE", &format!("/object/item/{}", id), None)?;
+        Ok(())
+    }
+}
+
+impl Drop for Serve {
+    fn drop(&mut self) {
+        self.process.kill().unwrap_or_else(|err| {
+            eprintln!("bitwarden: Failed to stop bw serve: {}", err);
+        });
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stand_in::StandIn;
+    use super::*;
+    use std::net::TcpListener;
+    use std::process::Command;
+
+    /// A session talking to the stand-in, with a harmless process to stop.
+    fn serve(stand_in: &StandIn) -> Serve {
+        Serve {
+            key: "key".to_owned(),
+            base_url: stand_in.url.clone(),
+            process: Command::new("sleep").arg("60").spawn().unwrap(),
+        }
+    }
+
+    fn answer(status: u16, body: &'static str) -> StandIn {
+        StandIn::start(move |_| (status, body.to_owned()))
+    }
+
+    #[test]
+    fn list_items() {
+        let stand_in = answer(
+            200,
+            r#"{"success":true,"data":{"object":"list","data":[{"object":"item",
+            "id":"1","name":"example","type":1,"favorite":false,"collectionIds":[],
+            "revisionDate":"2024-01-01T00:00:00.000Z",
+            "login":{"username":"me","password":"secret","uris":[]}}]}}"#,
+        );
+
+        let items = serve(&stand_in).list_items().unwrap();
+        assert_eq!(stand_in.requests(), vec!["GET /list/object/items"]);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "example");
+        assert_eq!(
+            items[0].login.as_ref().unwrap().username.as_deref(),
+            Some("me")
+        );
+    }
+
+    #[test]
+    fn read_field() {
+        let stand_in = answer(200, r#"{"success":true,"data":{"data":"123456"}}"#);
+
+        assert_eq!(serve(&stand_in).read_field("1", "totp").unwrap(), "123456");
+        assert_eq!(stand_in.requests(), vec!["GET /object/totp/1"]);
+    }
+
+    #[test]
+    fn answer_without_data() {
+        let stand_in = answer(200, r#"{"success":true,"data":null}"#);
+
+        serve(&stand_in).lock().unwrap();
+        assert_eq!(stand_in.requests(), vec!["POST /lock"]);
+    }
+
+    #[test]
+    fn failure_message() {
+        let stand_in = answer(
+            400,
+            r#"{"success":false,"message":"Invalid master password."}"#,
+        );
+
+        let err = serve(&stand_in).sync().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidPassword)
+        ));
+    }
+
+    #[test]
+    fn port_in_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let err = Serve::start(None, port, None).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::PortInUse(p)) if *p == port
+        ));
+    }
+}
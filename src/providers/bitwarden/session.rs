@@ -1,52 +1,132 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use serde::de::DeserializeOwned;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
 use std::error::Error as StdError;
 use std::fmt;
-use std::io::Write;
-use std::process::{Command, Stdio};
-use std::str;
+use std::path::Path;
 
-#[serde(rename_all = "camelCase")]
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Status {
-    server_url: String,
-    last_sync: DateTime<Utc>,
-    user_email: String,
-    user_id: String,
-    status: String,
+    pub server_url: Option<String>,
+    pub last_sync: Option<DateTime<Utc>>,
+    pub user_email: Option<String>,
+    pub user_id: Option<String>,
+    pub status: String,
 }
 
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-#[derive(Deserialize, Debug)]
 pub struct Folder {
     pub object: String,
     pub id: Option<String>, // None is the root folder.
     pub name: String,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Organization {
+    pub object: String,
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    pub object: String,
+    pub id: String,
+    pub organization_id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-#[derive(Deserialize, Debug)]
 pub struct Uri {
     pub uri: String,
+    /// See UriMatchType in the Bitwarden clients, None is the default.
     #[serde(rename = "match")]
-    pub match_: Option<String>,
+    pub match_: Option<i32>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-#[derive(Deserialize, Debug)]
 pub struct Login {
     pub username: Option<String>,
     pub password: Option<String>,
     pub totp: Option<String>,
     pub password_revision_date: Option<DateTime<Utc>>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "null_as_empty")]
     pub uris: Vec<Uri>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Card {
+    pub cardholder_name: Option<String>,
+    pub brand: Option<String>,
+    pub number: Option<String>,
+    pub exp_month: Option<String>,
+    pub exp_year: Option<String>,
+    pub code: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Identity {
+    pub title: Option<String>,
+    pub first_name: Option<String>,
+    pub middle_name: Option<String>,
+    pub last_name: Option<String>,
+    pub address1: Option<String>,
+    pub address2: Option<String>,
+    pub address3: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+    pub company: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub ssn: Option<String>,
+    pub username: Option<String>,
+    pub passport_number: Option<String>,
+    pub license_number: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SecureNote {
+    #[serde(rename = "type")]
+    pub type_: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomField {
+    pub name: Option<String>,
+    pub value: Option<String>,
+    /// 0 = text, 1 = hidden, 2 = boolean, 3 = linked.
+    #[serde(rename = "type")]
+    pub type_: i32,
+    pub linked_id: Option<i32>,
+}
+
+impl CustomField {
+    /// Whether the value is hidden, itself or as the password, security
+    /// code, card number or identity number a linked field points at.
+    pub fn is_secret(&self) -> bool {
+        match (self.type_, self.linked_id) {
+            (1, _) => true,
+            (3, Some(linked_id)) => [101, 303, 305, 412, 414, 415].contains(&linked_id),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-#[derive(Deserialize, Debug)]
 pub struct Item {
     pub object: String,
     pub id: String,
@@ -58,27 +138,280 @@ pub struct Item {
     pub notes: Option<String>,
     pub favorite: bool,
     pub login: Option<Login>,
+    pub card: Option<Card>,
+    pub identity: Option<Identity>,
+    pub secure_note: Option<SecureNote>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub fields: Vec<CustomField>,
     pub collection_ids: Vec<String>,
     pub revision_date: DateTime<Utc>,
+    pub creation_date: Option<DateTime<Utc>>,
+    /// Missing, rather than empty, without attachments.
+    pub attachments: Option<Vec<Attachment>>,
+    pub password_history: Option<Vec<PasswordHistory>>,
 }
 
-pub struct Session {
-    pub key: String,
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordHistory {
+    pub last_used_date: DateTime<Utc>,
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub id: String,
+    pub file_name: String,
+    pub size_name: Option<String>,
+}
+
+impl Item {
+    /// Value of a custom field, following linked fields to the value they
+    /// point at.
+    pub fn custom_field(&self, name: &str) -> Option<String> {
+        let field = self
+            .fields
+            .iter()
+            .find(|f| f.name.as_deref() == Some(name))?;
+        match field.linked_id {
+            Some(linked_id) if field.type_ == 3 => self.linked_value(linked_id),
+            _ => field.value.clone(),
+        }
+    }
+
+    /// See LoginLinkedId, CardLinkedId and IdentityLinkedId in the Bitwarden
+    /// clients.
+    fn linked_value(&self, linked_id: i32) -> Option<String> {
+        let login = self.login.as_ref();
+        let card = self.card.as_ref();
+        let identity = self.identity.as_ref();
+        match linked_id {
+            100 => login?.username.clone(),
+            101 => login?.password.clone(),
+            300 => card?.cardholder_name.clone(),
+            301 => card?.exp_month.clone(),
+            302 => card?.exp_year.clone(),
+            303 => card?.code.clone(),
+            304 => card?.brand.clone(),
+            305 => card?.number.clone(),
+            400 => identity?.title.clone(),
+            401 => identity?.middle_name.clone(),
+            402 => identity?.address1.clone(),
+            403 => identity?.address2.clone(),
+            404 => identity?.address3.clone(),
+            405 => identity?.city.clone(),
+            406 => identity?.state.clone(),
+            407 => identity?.postal_code.clone(),
+            408 => identity?.country.clone(),
+            409 => identity?.company.clone(),
+            410 => identity?.email.clone(),
+            411 => identity?.phone.clone(),
+            412 => identity?.ssn.clone(),
+            413 => identity?.username.clone(),
+            414 => identity?.passport_number.clone(),
+            415 => identity?.license_number.clone(),
+            416 => identity?.first_name.clone(),
+            417 => identity?.last_name.clone(),
+            _ => None,
+        }
+    }
+
+    /// Named values of card, identity and secure note items, which `bw get`
+    /// has no shortcut for.
+    pub fn extra_fields(&self) -> Vec<ExtraField> {
+        let mut fields = vec![];
+
+        if let Some(card) = &self.card {
+            let expiration = match (&card.exp_month, &card.exp_year) {
+                (Some(month), Some(year)) => Some(format!("{}/{}", month, year)),
+                (None, Some(year)) => Some(year.to_owned()),
+                _ => None,
+            };
+            push_field(&mut fields, "cardholder name", false, &card.cardholder_name);
+            push_field(&mut fields, "brand", false, &card.brand);
+            push_field(&mut fields, "number", true, &card.number);
+            push_field(&mut fields, "expiration", false, &expiration);
+            push_field(&mut fields, "security code", true, &card.code);
+        }
+
+        if let Some(identity) = &self.identity {
+            let name = join_some(
+                &[
+                    &identity.title,
+                    &identity.first_name,
+                    &identity.middle_name,
+                    &identity.last_name,
+                ],
+                " ",
+            );
+            let city = join_some(&[&identity.postal_code, &identity.city], " ");
+            let address = join_some(
+                &[
+                    &identity.address1,
+                    &identity.address2,
+                    &identity.address3,
+                    &city,
+                    &identity.state,
+                    &identity.country,
+                ],
+                ", ",
+            );
+            push_field(&mut fields, "name", false, &name);
+            push_field(&mut fields, "username", false, &identity.username);
+            push_field(&mut fields, "company", false, &identity.company);
+            push_field(&mut fields, "email", false, &identity.email);
+            push_field(&mut fields, "phone", false, &identity.phone);
+            push_field(&mut fields, "address", false, &address);
+            push_field(&mut fields, "ssn", true, &identity.ssn);
+            push_field(
+                &mut fields,
+                "passport number",
+                true,
+                &identity.passport_number,
+            );
+            push_field(
+                &mut fields,
+                "license number",
+                true,
+                &identity.license_number,
+            );
+        }
+
+        fields
+    }
+}
+
+/// A value of a card or identity, see `Item::extra_fields`.
+pub struct ExtraField {
+    pub name: &'static str,
+    pub value: String,
+    /// Asked for and copied like a password.
+    pub secret: bool,
+}
+
+/// A list the server sends as `null` when it is empty.
+pub fn null_as_empty<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+fn push_field(
+    fields: &mut Vec<ExtraField>,
+    name: &'static str,
+    secret: bool,
+    value: &Option<String>,
+) {
+    if let Some(value) = value {
+        if !value.is_empty() {
+            fields.push(ExtraField {
+                name,
+                value: value.to_owned(),
+                secret,
+            });
+        }
+    }
+}
+
+fn join_some(parts: &[&Option<String>], separator: &str) -> Option<String> {
+    let parts: Vec<&str> = parts
+        .iter()
+        .filter_map(|part| part.as_deref())
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(separator))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Error {
+    /// `bw` is not installed, or not in the PATH.
+    BwMissing,
+    NotLoggedIn,
+    InvalidPassword,
     UnlockFailed,
+    LoginFailed(String),
     FailedToDecrypt,
+    NotFound,
+    RateLimited,
+    Network(String),
+    /// The server wants a code of one of these two-step login providers,
+    /// by their number in TwoFactorProviderType.
+    TwoStepRequired(Vec<u8>),
+    /// The server emailed a code to verify this new device.
+    NewDeviceVerification,
+    /// The port for `bw serve` is taken, eg. by the `bw serve` of another
+    /// account.
+    PortInUse(u16),
+    /// What the backend cannot do.
+    Unsupported(&'static str),
     UnexpectedResponse(String),
 }
 
+impl Error {
+    /// Recognise an error message of `bw`, as printed on stderr or answered
+    /// by `bw serve` and the server.
+    pub fn from_message(message: &str) -> Error {
+        let message = message.trim();
+        let lower = message.to_lowercase();
+        let contains = |patterns: &[&str]| patterns.iter().any(|p| lower.contains(p));
+
+        if contains(&[
+            "invalid master password",
+            "username or password is incorrect",
+        ]) {
+            Error::InvalidPassword
+        } else if contains(&["you are not logged in"]) {
+            Error::NotLoggedIn
+        } else if contains(&["failed to decrypt"]) {
+            Error::FailedToDecrypt
+        } else if contains(&["too many requests", "rate limit"]) {
+            Error::RateLimited
+        } else if contains(&[
+            "econnrefused",
+            "econnreset",
+            "enotfound",
+            "etimedout",
+            "getaddrinfo",
+            "fetch failed",
+            "network",
+        ]) {
+            Error::Network(message.to_owned())
+        } else if contains(&["not found"]) {
+            Error::NotFound
+        } else {
+            Error::UnexpectedResponse(message.to_owned())
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Error::UnlockFailed => f.write_str("UnlockFailed"),
-            Error::FailedToDecrypt => f.write_str("FailedToDecrypt"),
-            Error::UnexpectedResponse(_) => f.write_str("UnexpectedResponse"),
+        match self {
+            Error::BwMissing => f.write_str("bw is not installed or not in the PATH"),
+            Error::NotLoggedIn => f.write_str("Not logged in"),
+            Error::InvalidPassword => f.write_str("Invalid master password"),
+            Error::UnlockFailed => f.write_str("Unlocking the vault failed"),
+            Error::LoginFailed(message) => write!(f, "Login failed: {}", message),
+            Error::FailedToDecrypt => f.write_str("Failed to decrypt"),
+            Error::NotFound => f.write_str("Not found"),
+            Error::RateLimited => f.write_str("Too many requests, try again later"),
+            Error::Network(message) => write!(f, "Network failure: {}", message),
+            Error::TwoStepRequired(_) => f.write_str("Two-step login required"),
+            Error::NewDeviceVerification => f.write_str("New device verification required"),
+            Error::PortInUse(port) => write!(
+                f,
+                "Port {} is already in use, every account needs its own port",
+                port
+            ),
+            Error::Unsupported(what) => write!(f, "{} is not supported by this backend", what),
+            Error::UnexpectedResponse(message) => write!(f, "Unexpected response: {}", message),
         }
     }
 }
@@ -86,101 +419,73 @@ impl fmt::Display for Error {
 impl StdError for Error {
     fn description(&self) -> &str {
         match *self {
+            Error::BwMissing => "BwMissing",
+            Error::NotLoggedIn => "NotLoggedIn",
+            Error::InvalidPassword => "InvalidPassword",
             Error::UnlockFailed => "UnlockFailed",
+            Error::LoginFailed(_) => "LoginFailed",
             Error::FailedToDecrypt => "FailedToDecrypt",
+            Error::NotFound => "NotFound",
+            Error::RateLimited => "RateLimited",
+            Error::Network(_) => "Network",
+            Error::TwoStepRequired(_) => "TwoStepRequired",
+            Error::NewDeviceVerification => "NewDeviceVerification",
+            Error::PortInUse(_) => "PortInUse",
+            Error::Unsupported(_) => "Unsupported",
             Error::UnexpectedResponse(_) => "UnexpectedResponse",
         }
     }
 }
 
-impl Session {
-    pub fn open(key: &str) -> Session {
-        Session {
-            key: key.to_string(),
-        }
-    }
-
-    pub fn unlock(password: &str) -> Result<Session> {
-        let mut p = Command::new("bw")
-            .args(&["unlock", "--raw"])
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?;
-
-        {
-            let input = password.as_bytes();
-            let stdin = p.stdin.as_mut().unwrap();
-            stdin.write_all(input)?;
-        }
-
-        let output = p.wait_with_output().unwrap().stdout;
-        let key = str::from_utf8(&output)?.to_string();
+pub trait Session {
+    /// Session key, as stored in the keyring.
+    fn key(&self) -> &str;
+    fn lock(&self) -> Result<()>;
+    fn sync(&self) -> Result<()>;
+    fn status(&self) -> Result<Status>;
+    fn list_folders(&self) -> Result<Vec<Folder>>;
+    fn list_organizations(&self) -> Result<Vec<Organization>>;
+    fn list_collections(&self) -> Result<Vec<Collection>>;
+    fn list_items(&self) -> Result<Vec<Item>>;
+    fn get_item(&self, id: &str) -> Result<Item>;
+    fn read_field(&self, id: &str, field: &str) -> Result<String>;
 
-        if key == "" {
-            return Err(anyhow::Error::from(Error::UnlockFailed));
-        }
-
-        Ok(Session { key })
-    }
-
-    pub fn is_unlocked(&self) -> Result<bool> {
+    fn is_unlocked(&self) -> Result<bool> {
         Ok(self.status()?.status == "unlocked")
     }
 
-    pub fn lock(&self) -> Result<()> {
-        let resp = self.call_str(&["lock"])?;
-        match &resp[..] {
-            "Your vault is locked." => Ok(()),
-            _ => Err(anyhow::Error::from(Error::UnexpectedResponse(resp))),
-        }
-    }
+    // Writing, which not every backend can. Items are passed as the JSON of
+    // `bw`, to keep the fields this crate does not know about.
 
-    pub fn sync(&self) -> Result<()> {
-        self.call_json(&["sync"])
+    fn get_raw_item(&self, _id: &str) -> Result<Value> {
+        Err(Error::Unsupported("Editing items").into())
     }
 
-    pub fn status(&self) -> Result<Status> {
-        self.call_json(&["status"])
+    fn create_item(&self, _item: &Value) -> Result<Item> {
+        Err(Error::Unsupported("Creating items").into())
     }
 
-    pub fn list_folders(&self) -> Result<Vec<Folder>> {
-        self.call_json(&["list", "folders"])
+    fn edit_item(&self, _id: &str, _item: &Value) -> Result<Item> {
+        Err(Error::Unsupported("Editing items").into())
     }
 
-    pub fn list_items(&self) -> Result<Vec<Item>> {
-        self.call_json(&["list", "items"])
+    fn create_folder(&self, _name: &str) -> Result<Folder> {
+        Err(Error::Unsupported("Creating folders").into())
     }
 
-    pub fn read_field(&self, id: &str, field: &str) -> Result<String> {
-        self.call_str(&["get", id, field])
+    /// Download and decrypt an attachment to a file.
+    fn get_attachment(&self, _item_id: &str, _id: &str, _output: &Path) -> Result<()> {
+        Err(Error::Unsupported("Downloading attachments").into())
     }
 
-    fn call_str(&self, args: &[&str]) -> Result<String> {
-        let p = Command::new("bw")
-            .args(args)
-            .env("BW_SESSION", &self.key)
-            .stdin(Stdio::null())
-            .output()?;
-
-        let output = str::from_utf8(&p.stdout)?;
-        self.check_for_errors(&output)?;
-        Ok(output.to_string())
-    }
-
-    fn call_json<T>(&self, args: &[&str]) -> Result<T>
-    where
-        T: DeserializeOwned,
-    {
-        let output = self.call_str(args)?;
-        Ok(serde_json::from_str(&output)?)
+    /// Create a Send from the JSON of `bw send template`, with the file to
+    /// send if any. Gives the created Send.
+    fn create_send(&self, _send: &Value, _file: Option<&Path>) -> Result<Value> {
+        Err(Error::Unsupported("Creating a Send").into())
     }
 
-    fn check_for_errors(&self, output: &str) -> std::result::Result<(), Error> {
-        if output.starts_with("Failed to decrypt.") {
-            Err(Error::FailedToDecrypt)
-        } else {
-            Ok(())
-        }
+    /// Move an item to the trash.
+    fn delete_item(&self, _id: &str) -> Result<()> {
+        Err(Error::Unsupported("Deleting items").into())
     }
 }
@@ -4,8 +4,8 @@ use crate::provider::Provider;
 use anyhow::Result;
 use serde::Deserialize;
 
-#[serde(rename_all = "camelCase")]
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Config {
     path: String,
     wrapper: Vec<String>,
@@ -34,5 +34,7 @@ impl Provider for Keyhub {
         Ok(vec![])
     }
 
-    fn do_action(&mut self, action: &Action) {}
+    fn do_action(&mut self, action: &Action) -> Result<()> {
+        Ok(())
+    }
 }
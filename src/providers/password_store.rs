@@ -1,41 +1,536 @@
 use crate::app::App;
-use crate::item::{Action, Field, Item};
+use crate::generator;
+use crate::generator::Options;
+use crate::item::{Action, Field, Item, ItemAction, Kind, NewLogin};
 use crate::provider::Provider;
-use anyhow::Result;
+use crate::providers::expand_home;
+use crate::report::Password;
+use anyhow::{anyhow, Result};
 use serde::Deserialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str;
 
 // TODO: for password-store provider: fields are NOT known before hand.
 //       all files need to be decrypted to discover the fields, and that takes too long.
 //       change cache to optionally store fields?
 
-#[serde(rename_all = "camelCase")]
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Config {
     path: String,
+    /// Words for generated passphrases, one per line.
+    wordlist: Option<String>,
+}
+
+pub struct PasswordStore {
+    path: PathBuf,
+    wordlist: Option<PathBuf>,
 }
 
-pub struct PasswordStore {}
+/// A decrypted pass entry: the password on the first line, followed by
+/// `key: value` metadata lines. Everything after the metadata are notes.
+///
+/// The lines are kept as they are, so editing a field leaves the rest of the
+/// entry untouched.
+struct Entry {
+    lines: Vec<String>,
+}
+
+/// What a line after the password holds.
+enum Line<'a> {
+    /// A `key: value` line, or a URL on its own line, which has no key.
+    Field {
+        key: Option<&'a str>,
+        value: &'a str,
+    },
+    Blank,
+    /// A line that starts the notes, everything from there on are notes.
+    Note,
+}
 
 impl PasswordStore {
     pub fn new(app: &App, id: &str, config: serde_json::Value) -> Box<dyn Provider> {
         let config: Config = serde_json::from_value(config).unwrap();
         println!("pass config = {:?}", config);
-        Box::new(PasswordStore {})
+        Box::new(PasswordStore {
+            path: expand_home(&config.path),
+            wordlist: config.wordlist.as_deref().map(expand_home),
+        })
+    }
+
+    fn show(&self, item: &Item) -> Result<Entry> {
+        let output = Command::new("pass")
+            .args(["show", &item.id])
+            .env("PASSWORD_STORE_DIR", &self.path)
+            .stdin(Stdio::null())
+            .output()?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "pass show {} failed: {}",
+                item.id,
+                str::from_utf8(&output.stderr)?.trim()
+            ));
+        }
+
+        Ok(Entry::parse(str::from_utf8(&output.stdout)?))
+    }
+
+    fn run(&self, args: &[&str]) -> Result<()> {
+        let output = Command::new("pass")
+            .args(args)
+            .env("PASSWORD_STORE_DIR", &self.path)
+            .stdin(Stdio::null())
+            .output()?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "pass {} failed: {}",
+                args.join(" "),
+                str::from_utf8(&output.stderr)?.trim()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write an entry with `pass insert`, overwriting an existing one.
+    fn insert(&self, id: &str, entry: &Entry) -> Result<()> {
+        let mut p = Command::new("pass")
+            .args(["insert", "--multiline", "--force", id])
+            .env("PASSWORD_STORE_DIR", &self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        {
+            let stdin = p.stdin.as_mut().unwrap();
+            stdin.write_all(entry.contents().as_bytes())?;
+        }
+
+        let output = p.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "pass insert {} failed: {}",
+                id,
+                str::from_utf8(&output.stderr)?.trim()
+            ));
+        }
+        Ok(())
     }
 }
 
+impl Entry {
+    fn new(password: &str) -> Entry {
+        Entry {
+            lines: vec![password.to_owned()],
+        }
+    }
+
+    fn parse(contents: &str) -> Entry {
+        let mut entry = Entry::new("");
+        for (n, line) in contents.lines().enumerate() {
+            if n == 0 {
+                entry.lines[0] = line.to_owned();
+            } else {
+                entry.lines.push(line.to_owned());
+            }
+        }
+        entry
+    }
+
+    /// The entry as stored by pass, the reverse of `parse`.
+    fn contents(&self) -> String {
+        self.lines.join("\n") + "\n"
+    }
+
+    /// The metadata fields with their line and value, and the line the
+    /// notes start at.
+    fn layout(&self) -> (Vec<(usize, Field, String)>, Option<usize>) {
+        let mut fields = vec![];
+        for (n, line) in self.lines.iter().enumerate().skip(1) {
+            match classify(line) {
+                Line::Field { key, value } => {
+                    fields.push((n, field_of(key, value), value.to_owned()))
+                }
+                Line::Blank => {}
+                Line::Note => return (fields, Some(n)),
+            }
+        }
+        (fields, None)
+    }
+
+    /// Set a field, rewriting only its line. A new field goes after the
+    /// other metadata.
+    fn set(&mut self, field: &Field, value: &str) -> Result<()> {
+        let (fields, notes) = self.layout();
+        match field {
+            Field::Password => self.lines[0] = value.to_owned(),
+            Field::Notes => {
+                self.lines.truncate(notes.unwrap_or(self.lines.len()));
+                self.lines.extend(value.lines().map(str::to_owned));
+            }
+            Field::Hidden(name) => return Err(anyhow!("pass has no hidden field {}", name)),
+            _ => match fields.iter().find(|(_, f, _)| f == field) {
+                Some((n, _, _)) => {
+                    self.lines[*n] = match classify(&self.lines[*n]) {
+                        Line::Field { key: Some(key), .. } => format!("{}: {}", key, value),
+                        _ => format_field(default_key(field), value),
+                    };
+                }
+                None => {
+                    let n = fields.last().map_or(1, |(n, _, _)| n + 1);
+                    let line = format_field(default_key(field), value);
+                    self.lines.insert(n, line);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn fields(&self) -> Vec<Field> {
+        let (metadata, notes) = self.layout();
+        let mut fields = vec![];
+        if !self.lines[0].is_empty() {
+            fields.push(Field::Password);
+        }
+        for (_, field, _) in metadata {
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+        if notes.is_some() {
+            fields.push(Field::Notes);
+        }
+        fields
+    }
+
+    fn get(&self, field: &Field) -> Option<String> {
+        let (metadata, notes) = self.layout();
+        match field {
+            Field::Password => Some(self.lines[0].clone()),
+            Field::Notes => notes.map(|n| self.lines[n..].join("\n")),
+            Field::Hidden(_) => None,
+            _ => metadata
+                .into_iter()
+                .find(|(_, f, _)| f == field)
+                .map(|(_, _, value)| value),
+        }
+    }
+}
+
+fn classify(line: &str) -> Line<'_> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return Line::Blank;
+    }
+    // Not a key "https" with the value "//host".
+    if let Some((scheme, _)) = trimmed.split_once("://") {
+        let is_scheme = !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        if is_scheme {
+            return Line::Field {
+                key: None,
+                value: trimmed,
+            };
+        }
+    }
+    match line.split_once(':') {
+        Some((key, value)) if !key.trim().is_empty() && !key.contains(' ') => Line::Field {
+            key: Some(key.trim()),
+            value: value.trim(),
+        },
+        _ => Line::Note,
+    }
+}
+
+/// The field of a metadata line. Of the URLs on their own line, those of
+/// TOTP secrets are the TOTP field, others the url field.
+fn field_of(key: Option<&str>, value: &str) -> Field {
+    let key = match key {
+        Some(key) => key,
+        None if value.starts_with("otpauth://") => return Field::Totp,
+        None => return Field::Other("url".to_owned()),
+    };
+    match &key.to_lowercase()[..] {
+        "user" | "username" | "login" => Field::Username,
+        "otpauth" | "totp" | "otp" => Field::Totp,
+        _ => Field::Other(key.to_owned()),
+    }
+}
+
+/// The key for a new line of the field.
+fn default_key(field: &Field) -> &str {
+    match field {
+        Field::Username => "user",
+        Field::Totp => "totp",
+        field => field.name(),
+    }
+}
+
+/// A new metadata line. URLs that are known by their field without a key
+/// go on a line of their own.
+fn format_field(key: &str, value: &str) -> String {
+    if (key == "totp" && value.starts_with("otpauth://")) || (key == "url" && value.contains("://"))
+    {
+        value.to_owned()
+    } else {
+        format!("{}: {}", key, value)
+    }
+}
+
+fn walk(root: &Path, dir: &Path, items: &mut Vec<Item>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+        if hidden {
+            continue;
+        } else if path.is_dir() {
+            walk(root, &path, items)?;
+        } else if path.extension().is_some_and(|ext| ext == "gpg") {
+            let id = path
+                .strip_prefix(root)?
+                .with_extension("")
+                .to_string_lossy()
+                .into_owned();
+            items.push(Item {
+                title: id.clone(),
+                id,
+                fields: vec![],
+                kind: Kind::Login,
+                uris: vec![],
+                favorite: false,
+                attachments: vec![],
+            });
+        }
+    }
+    Ok(())
+}
+
 impl Provider for PasswordStore {
     fn list_items(&mut self) -> Result<Vec<Item>> {
-        Ok(vec![])
+        let mut items = vec![];
+        walk(&self.path, &self.path, &mut items)?;
+        Ok(items)
     }
 
     fn read_field(&mut self, item: &Item, field: &Field) -> Result<String> {
-        Ok("".to_owned())
+        self.show(item)?
+            .get(field)
+            .ok_or_else(|| anyhow!("{} has no {} field", item.title, field.name()))
+    }
+
+    fn list_fields(&mut self, item: &Item) -> Result<Vec<Field>> {
+        Ok(self.show(item)?.fields())
+    }
+
+    /// Decrypts every entry once, rather than for its fields and again for
+    /// the password.
+    fn list_passwords(&mut self) -> Result<Vec<Password>> {
+        let mut passwords = vec![];
+        for item in self.list_items()? {
+            let password = self.show(&item)?.get(&Field::Password).unwrap_or_default();
+            if !password.is_empty() {
+                passwords.push(Password {
+                    item,
+                    password,
+                    revision_date: None,
+                });
+            }
+        }
+        Ok(passwords)
     }
 
     fn list_actions(&mut self) -> Result<Vec<Action>> {
-        Ok(vec![])
+        Ok(vec![Action {
+            title: generator::ACTION.to_owned(),
+            shortcut: "Alt+g".to_owned(),
+        }])
+    }
+
+    fn do_action(&mut self, action: &Action) -> Result<()> {
+        if action.title == generator::ACTION {
+            if let Some(options) = generator::ask()? {
+                let password = self.generate_password(&options)?;
+                generator::deliver(&password)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_password(&mut self, options: &Options) -> Result<String> {
+        generator::generate(options, self.wordlist.as_deref())
+    }
+
+    fn is_writable(&self) -> bool {
+        true
+    }
+
+    fn list_folders(&mut self) -> Result<Vec<String>> {
+        let mut folders: Vec<String> = self
+            .list_items()?
+            .into_iter()
+            .filter_map(|i| {
+                i.title
+                    .rsplit_once('/')
+                    .map(|(folder, _)| folder.to_owned())
+            })
+            .collect();
+        folders.sort();
+        folders.dedup();
+        Ok(folders)
+    }
+
+    fn create_item(&mut self, login: &NewLogin) -> Result<Item> {
+        let id = match &login.folder {
+            Some(folder) => format!("{}/{}", folder, login.name),
+            None => login.name.clone(),
+        };
+        if self.path.join(format!("{}.gpg", id)).exists() {
+            return Err(anyhow!("{} already exists", id));
+        }
+
+        let mut entry = Entry::new(&login.password);
+        if let Some(username) = &login.username {
+            entry.set(&Field::Username, username)?;
+        }
+        if let Some(uri) = &login.uri {
+            entry.set(&Field::Other("url".to_owned()), uri)?;
+        }
+        self.insert(&id, &entry)?;
+
+        Ok(Item {
+            title: id.clone(),
+            id,
+            fields: entry.fields(),
+            kind: Kind::Login,
+            uris: vec![],
+            favorite: false,
+            attachments: vec![],
+        })
     }
 
-    fn do_action(&mut self, action: &Action) {}
+    fn update_field(&mut self, item: &Item, field: &Field, value: &str) -> Result<()> {
+        let mut entry = self.show(item)?;
+        entry.set(field, value)?;
+        self.insert(&item.id, &entry)
+    }
+
+    fn item_actions(&self) -> Vec<ItemAction> {
+        vec![ItemAction::Move, ItemAction::Delete]
+    }
+
+    fn move_item(&mut self, item: &Item, folder: Option<&str>) -> Result<()> {
+        let name = item.id.rsplit('/').next().unwrap_or_default();
+        let id = match folder {
+            Some(folder) => format!("{}/{}", folder, name),
+            None => name.to_owned(),
+        };
+        if id == item.id {
+            return Ok(());
+        }
+        // Without --force pass would ask before overwriting.
+        if self.path.join(format!("{}.gpg", id)).exists() {
+            return Err(anyhow!("{} already exists", id));
+        }
+        self.run(&["mv", &item.id, &id])
+    }
+
+    fn delete_item(&mut self, item: &Item) -> Result<()> {
+        self.run(&["rm", "--force", &item.id])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &str = "hunter2\n\
+                            Username: alice\n\
+                            \n\
+                            https://example.com/login\n\
+                            otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP\n\
+                            pin: 1234\n\
+                            \n\
+                            Recovery codes below.\n\
+                            \n\
+                            abc-def\n";
+
+    #[test]
+    fn parse() {
+        let entry = Entry::parse(CONTENTS);
+        assert_eq!(
+            entry.fields(),
+            vec![
+                Field::Password,
+                Field::Username,
+                Field::Other("url".to_owned()),
+                Field::Totp,
+                Field::Other("pin".to_owned()),
+                Field::Notes,
+            ]
+        );
+        assert_eq!(entry.get(&Field::Username).as_deref(), Some("alice"));
+        assert_eq!(
+            entry.get(&Field::Other("url".to_owned())).as_deref(),
+            Some("https://example.com/login")
+        );
+        assert_eq!(
+            entry.get(&Field::Notes).as_deref(),
+            Some("Recovery codes below.\n\nabc-def")
+        );
+        assert_eq!(Entry::parse(CONTENTS).contents(), CONTENTS);
+    }
+
+    #[test]
+    fn set_rewrites_only_the_field() {
+        let mut entry = Entry::parse(CONTENTS);
+        entry.set(&Field::Username, "bob").unwrap();
+        entry.set(&Field::Other("pin".to_owned()), "4321").unwrap();
+        entry
+            .set(&Field::Totp, "otpauth://totp/Example?secret=GEZDGNBV")
+            .unwrap();
+        assert_eq!(
+            entry.contents(),
+            CONTENTS
+                .replace("alice", "bob")
+                .replace("1234", "4321")
+                .replace("JBSWY3DPEHPK3PXP", "GEZDGNBV")
+        );
+
+        entry.set(&Field::Notes, "No more codes.").unwrap();
+        assert!(entry.contents().ends_with("pin: 4321\n\nNo more codes.\n"));
+    }
+
+    #[test]
+    fn set_adds_missing_fields() {
+        let mut entry = Entry::parse("hunter2\nurl: example.com\nSome notes\n");
+        entry.set(&Field::Username, "alice").unwrap();
+        entry
+            .set(
+                &Field::Totp,
+                "otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP",
+            )
+            .unwrap();
+        assert_eq!(
+            entry.contents(),
+            "hunter2\n\
+             url: example.com\n\
+             user: alice\n\
+             otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP\n\
+             Some notes\n"
+        );
+
+        let mut entry = Entry::new("hunter2");
+        entry.set(&Field::Notes, "first\nsecond").unwrap();
+        assert_eq!(entry.contents(), "hunter2\nfirst\nsecond\n");
+    }
 }
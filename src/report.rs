@@ -0,0 +1,146 @@
+//! Vault health report: reused, weak and old passwords. Everything is
+//! computed locally, passwords are only compared by their hash.
+
+use crate::item::Item;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    /// Passwords with fewer bits of estimated entropy are weak.
+    #[serde(default = "default_min_entropy")]
+    pub min_entropy: f64,
+    /// Passwords set longer ago are old.
+    #[serde(default = "default_max_age_days")]
+    pub max_age_days: i64,
+}
+
+fn default_min_entropy() -> f64 {
+    60.0
+}
+
+fn default_max_age_days() -> i64 {
+    365
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            min_entropy: default_min_entropy(),
+            max_age_days: default_max_age_days(),
+        }
+    }
+}
+
+/// The password of an item, as read by its provider.
+pub struct Password {
+    pub item: Item,
+    pub password: String,
+    /// When the password was set, if the provider knows.
+    pub revision_date: Option<DateTime<Utc>>,
+}
+
+pub enum Problem {
+    /// Used by this many other items.
+    Reused(usize),
+    /// Estimated bits of entropy.
+    Weak(f64),
+    /// Days since the password was set.
+    Old(i64),
+}
+
+impl Problem {
+    pub fn describe(&self) -> String {
+        match self {
+            Problem::Reused(1) => "reused by 1 other item".to_owned(),
+            Problem::Reused(n) => format!("reused by {} other items", n),
+            Problem::Weak(bits) => format!("weak (~{:.0} bits)", bits),
+            Problem::Old(days) => format!("{} days old", days),
+        }
+    }
+}
+
+/// An item with at least one problem.
+pub struct Finding {
+    pub item: Item,
+    pub problems: Vec<Problem>,
+}
+
+/// Problems of the passwords, the items with most problems first.
+pub fn check(passwords: Vec<Password>, config: &Config) -> Vec<Finding> {
+    let hashes: Vec<Vec<u8>> = passwords
+        .iter()
+        .map(|p| Sha256::digest(p.password.as_bytes()).to_vec())
+        .collect();
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for hash in hashes.iter() {
+        *counts.entry(hash).or_default() += 1;
+    }
+
+    let now = Utc::now();
+    let mut findings: Vec<Finding> = passwords
+        .into_iter()
+        .zip(hashes.iter())
+        .filter_map(|(p, hash)| {
+            let mut problems = vec![];
+            match counts[&hash[..]] {
+                1 => {}
+                n => problems.push(Problem::Reused(n - 1)),
+            }
+            let bits = entropy(&p.password);
+            if bits < config.min_entropy {
+                problems.push(Problem::Weak(bits));
+            }
+            if let Some(date) = p.revision_date {
+                let days = (now - date).num_days();
+                if days > config.max_age_days {
+                    problems.push(Problem::Old(days));
+                }
+            }
+
+            if problems.is_empty() {
+                None
+            } else {
+                Some(Finding {
+                    item: p.item,
+                    problems,
+                })
+            }
+        })
+        .collect();
+
+    findings.sort_by(|a, b| {
+        b.problems
+            .len()
+            .cmp(&a.problems.len())
+            .then_with(|| a.item.title.cmp(&b.item.title))
+    });
+    findings
+}
+
+/// Rough entropy of a password: its length times the bits per character of
+/// the character classes it uses. This overrates passphrases and dictionary
+/// words, but catches short and simple passwords.
+pub fn entropy(password: &str) -> f64 {
+    let mut pool = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password.chars().any(|c| !c.is_ascii_alphanumeric()) {
+        pool += 33;
+    }
+
+    match pool {
+        0 => 0.0,
+        pool => password.chars().count() as f64 * (pool as f64).log2(),
+    }
+}
@@ -104,6 +104,13 @@ impl RofiWindow {
         self
     }
 
+    /// Pre-fill the filter with a query.
+    pub fn filter(mut self, query: &str) -> Self {
+        self.extra_args
+            .extend(vec!["-filter".to_string(), query.to_string()]);
+        self
+    }
+
     pub fn matching(mut self, algo: &str) -> Self {
         self.extra_args
             .extend(vec!["-matching".to_string(), algo.to_string()]);
@@ -147,6 +154,47 @@ impl RofiWindow {
     }
 }
 
+/// Ask for a line of text. Gives `None` when cancelled.
+pub fn ask(prompt: &str, message: Option<&str>, password: bool) -> Result<Option<String>> {
+    let mut window = RofiWindow::new(prompt)
+        .add_args(vec!["-dmenu"])
+        .password(password)
+        .lines(0);
+    if let Some(message) = message {
+        window = window.message(message);
+    }
+    Ok(match window.show(vec![])? {
+        RofiResponse::Entry(s) => Some(s),
+        _ => None,
+    })
+}
+
+/// Choose one of the options, or type another one. Gives `None` when
+/// cancelled.
+pub fn choose(prompt: &str, options: Vec<String>) -> Result<Option<String>> {
+    let res = RofiWindow::new(prompt)
+        .add_args(vec!["-dmenu"])
+        .lines(options.len().min(15) as i32)
+        .show(options)?;
+    Ok(match res {
+        RofiResponse::Entry(s) => Some(s),
+        _ => None,
+    })
+}
+
+/// Show an error message until it is dismissed.
+pub fn show_error(message: &str) -> Result<()> {
+    Command::new("rofi").args(["-e", message]).status()?;
+    Ok(())
+}
+
+/// Escape text for use in rows when `-markup-rows` is enabled.
+pub fn escape_markup(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 impl RofiResponse {
     pub fn entry(&self) -> Result<String> {
         match self {
@@ -0,0 +1,364 @@
+//! TOTP/HOTP code generation, see RFC 4226 and RFC 6238.
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+const STEAM_ALPHABET: &[u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+
+/// More digits than this do not fit in the 31 bits HOTP truncates to.
+const MAX_DIGITS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Totp { period: u64 },
+    Hotp { counter: u64 },
+    Steam,
+}
+
+#[derive(Debug, Clone)]
+pub struct Totp {
+    secret: Vec<u8>,
+    digits: u32,
+    algorithm: Algorithm,
+    kind: Kind,
+}
+
+impl Totp {
+    /// Parse an `otpauth://` URI, a Bitwarden `steam://` secret or a raw
+    /// base32 secret.
+    pub fn parse(s: &str) -> Result<Totp> {
+        let s = s.trim();
+
+        if let Some(secret) = s.strip_prefix("steam://") {
+            return Ok(Totp {
+                secret: base32_decode(secret)?,
+                digits: 5,
+                algorithm: Algorithm::Sha1,
+                kind: Kind::Steam,
+            });
+        }
+
+        if !s.starts_with("otpauth://") {
+            return Ok(Totp {
+                secret: base32_decode(s)?,
+                digits: 6,
+                algorithm: Algorithm::Sha1,
+                kind: Kind::Totp { period: 30 },
+            });
+        }
+
+        let url = Url::parse(s)?;
+        let mut totp = Totp {
+            secret: vec![],
+            digits: 6,
+            algorithm: Algorithm::Sha1,
+            kind: match url.host_str() {
+                Some("totp") => Kind::Totp { period: 30 },
+                Some("hotp") => Kind::Hotp { counter: 0 },
+                _ => return Err(anyhow!("Unsupported otpauth type in {}", url)),
+            },
+        };
+
+        for (key, value) in url.query_pairs() {
+            match &key.to_lowercase()[..] {
+                "secret" => totp.secret = base32_decode(&value)?,
+                "digits" => {
+                    totp.digits = value.parse()?;
+                    if totp.digits == 0 || totp.digits > MAX_DIGITS {
+                        return Err(anyhow!("Unsupported number of digits {}", value));
+                    }
+                }
+                "algorithm" => {
+                    totp.algorithm = match &value.to_uppercase()[..] {
+                        "SHA1" => Algorithm::Sha1,
+                        "SHA256" => Algorithm::Sha256,
+                        "SHA512" => Algorithm::Sha512,
+                        _ => return Err(anyhow!("Unsupported algorithm {}", value)),
+                    }
+                }
+                "period" => {
+                    if let Kind::Totp { .. } = totp.kind {
+                        let period = value.parse()?;
+                        if period == 0 {
+                            return Err(anyhow!("Period of 0 seconds in otpauth URI"));
+                        }
+                        totp.kind = Kind::Totp { period };
+                    }
+                }
+                "counter" => {
+                    if let Kind::Hotp { .. } = totp.kind {
+                        totp.kind = Kind::Hotp {
+                            counter: value.parse()?,
+                        };
+                    }
+                }
+                "encoder" if value == "steam" => {
+                    totp.kind = Kind::Steam;
+                    totp.digits = 5;
+                }
+                _ => {}
+            }
+        }
+
+        if totp.secret.is_empty() {
+            return Err(anyhow!("No secret in otpauth URI"));
+        }
+
+        Ok(totp)
+    }
+
+    /// The counter of an HOTP code, which has to be advanced after every
+    /// use.
+    pub fn counter(&self) -> Option<u64> {
+        match self.kind {
+            Kind::Hotp { counter } => Some(counter),
+            _ => None,
+        }
+    }
+
+    /// Generate the code for the current time, together with the number of
+    /// seconds it remains valid. HOTP codes do not expire.
+    pub fn now(&self) -> (String, Option<u64>) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.at(now)
+    }
+
+    /// Like `now`, but waits for the next code when the current one is valid
+    /// for less than `min_remaining` seconds.
+    pub fn fresh(&self, min_remaining: u64) -> (String, Option<u64>) {
+        match self.now() {
+            (_, Some(remaining)) if remaining < min_remaining => {
+                thread::sleep(Duration::from_secs(remaining));
+                self.now()
+            }
+            code => code,
+        }
+    }
+
+    pub fn at(&self, time: u64) -> (String, Option<u64>) {
+        match self.kind {
+            Kind::Totp { period } => (
+                self.format(self.hotp(time / period)),
+                Some(period - time % period),
+            ),
+            Kind::Steam => (self.format(self.hotp(time / 30)), Some(30 - time % 30)),
+            Kind::Hotp { counter } => (self.format(self.hotp(counter)), None),
+        }
+    }
+
+    fn hotp(&self, counter: u64) -> u32 {
+        let counter = counter.to_be_bytes();
+        let hash = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<sha1::Sha1>>(&self.secret, &counter),
+            Algorithm::Sha256 => hmac::<Hmac<sha2::Sha256>>(&self.secret, &counter),
+            Algorithm::Sha512 => hmac::<Hmac<sha2::Sha512>>(&self.secret, &counter),
+        };
+
+        let offset = (hash[hash.len() - 1] & 0xf) as usize;
+        ((hash[offset] & 0x7f) as u32) << 24
+            | (hash[offset + 1] as u32) << 16
+            | (hash[offset + 2] as u32) << 8
+            | hash[offset + 3] as u32
+    }
+
+    fn format(&self, binary: u32) -> String {
+        match self.kind {
+            Kind::Steam => {
+                let mut code = String::new();
+                let mut value = binary as usize;
+                for _ in 0..self.digits {
+                    code.push(STEAM_ALPHABET[value % STEAM_ALPHABET.len()] as char);
+                    value /= STEAM_ALPHABET.len();
+                }
+                code
+            }
+            _ => {
+                let code = binary as u64 % 10u64.pow(self.digits);
+                format!("{:0width$}", code, width = self.digits as usize)
+            }
+        }
+    }
+}
+
+/// The `otpauth://` URI with its counter set to `counter`, leaving the other
+/// parameters as they are.
+pub fn set_counter(uri: &str, counter: u64) -> Result<String> {
+    let mut url = Url::parse(uri.trim())?;
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key.to_lowercase() != "counter")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("counter", &counter.to_string());
+    Ok(url.to_string())
+}
+
+fn hmac<M>(key: &[u8], data: &[u8]) -> Vec<u8>
+where
+    M: Mac + hmac::digest::KeyInit,
+{
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// RFC 4648 base32, ignoring padding, whitespace and case.
+fn base32_decode(s: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in s
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=' && *c != '-')
+    {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => return Err(anyhow!("Invalid base32 character '{}'", c)),
+        };
+        buffer = buffer << 5 | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ASCII secrets of the RFC 6238 test vectors, in base32.
+    const SHA1_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    const SHA256_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA";
+    const SHA512_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+                                 GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA";
+
+    #[test]
+    fn rfc4226_vectors() {
+        let codes = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in codes.iter().enumerate() {
+            let uri = format!(
+                "otpauth://hotp/Test?secret={}&counter={}",
+                SHA1_SECRET, counter
+            );
+            let totp = Totp::parse(&uri).unwrap();
+            assert_eq!(totp.counter(), Some(counter as u64));
+            assert_eq!(totp.now(), (code.to_string(), None));
+        }
+    }
+
+    #[test]
+    fn rfc6238_vectors() {
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        let parse = |algorithm, secret| {
+            Totp::parse(&format!(
+                "otpauth://totp/Test?secret={}&algorithm={}&digits=8&period=30",
+                secret, algorithm
+            ))
+            .unwrap()
+        };
+        let sha1 = parse("SHA1", SHA1_SECRET);
+        let sha256 = parse("SHA256", SHA256_SECRET);
+        let sha512 = parse("SHA512", SHA512_SECRET);
+
+        for (time, sha1_code, sha256_code, sha512_code) in vectors.iter() {
+            let remaining = Some(30 - time % 30);
+            assert_eq!(sha1.at(*time), (sha1_code.to_string(), remaining));
+            assert_eq!(sha256.at(*time), (sha256_code.to_string(), remaining));
+            assert_eq!(sha512.at(*time), (sha512_code.to_string(), remaining));
+        }
+    }
+
+    #[test]
+    fn steam_vectors() {
+        // Steam Guard codes over the RFC 6238 SHA1 secret, whose truncated
+        // HOTP values are those of RFC 4226, e.g. 1094287082 at time 59.
+        let vectors = [
+            (59, "PV9M4"),
+            (1111111109, "PY4YB"),
+            (1234567890, "VHHQY"),
+            (2000000000, "9N776"),
+        ];
+        let steam = Totp::parse(&format!("steam://{}", SHA1_SECRET)).unwrap();
+        let encoder = Totp::parse(&format!(
+            "otpauth://totp/Steam:user?secret={}&encoder=steam",
+            SHA1_SECRET
+        ))
+        .unwrap();
+
+        for (time, code) in vectors.iter() {
+            let remaining = Some(30 - time % 30);
+            assert_eq!(steam.at(*time), (code.to_string(), remaining));
+            assert_eq!(encoder.at(*time), (code.to_string(), remaining));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let parse = |query: &str| {
+            Totp::parse(&format!(
+                "otpauth://totp/Test?secret={}&{}",
+                SHA1_SECRET, query
+            ))
+        };
+        assert!(parse("period=0").is_err());
+        assert!(parse("digits=0").is_err());
+        assert!(parse("digits=11").is_err());
+        assert!(parse("digits=20").is_err());
+        assert_eq!(parse("digits=10").unwrap().at(59).0, "1094287082");
+    }
+
+    #[test]
+    fn advances_counter() {
+        let uri = format!(
+            "otpauth://hotp/Example:user?secret={}&issuer=Example&counter=4",
+            SHA1_SECRET
+        );
+        let next = set_counter(&uri, 5).unwrap();
+        assert_eq!(
+            next,
+            format!(
+                "otpauth://hotp/Example:user?secret={}&issuer=Example&counter=5",
+                SHA1_SECRET
+            )
+        );
+        assert_eq!(Totp::parse(&next).unwrap().now().0, "254676");
+
+        // A missing counter starts at 0.
+        let uri = format!("otpauth://hotp/Test?secret={}", SHA1_SECRET);
+        assert_eq!(Totp::parse(&uri).unwrap().counter(), Some(0));
+        assert!(set_counter(&uri, 1).unwrap().ends_with("&counter=1"));
+    }
+}
@@ -0,0 +1,293 @@
+//! The window that had focus before rofi opened.
+//!
+//! On X11 this is read from the `_NET_ACTIVE_WINDOW` of the root window, on
+//! sway and Hyprland from the focused node through their IPC.
+
+use regex::Regex;
+use std::env;
+use std::process::{Command, Stdio};
+use std::str;
+
+/// Window classes of browsers, which tell nothing about the site.
+const BROWSERS: &[&str] = &[
+    "firefox",
+    "librewolf",
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "brave-browser",
+    "qutebrowser",
+    "vivaldi-stable",
+];
+
+lazy_static! {
+    static ref HOST: Regex = Regex::new(r"(?i)\b(?:[a-z0-9-]+\.)+[a-z]{2,}(?::\d+)?\b").unwrap();
+}
+
+#[derive(Debug)]
+pub struct ActiveWindow {
+    pub title: String,
+    pub class: String,
+}
+
+impl ActiveWindow {
+    pub fn get() -> Option<ActiveWindow> {
+        if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            hyprland()
+        } else if env::var_os("SWAYSOCK").is_some() {
+            sway()
+        } else {
+            x11()
+        }
+    }
+
+    /// Text to match URIs against.
+    pub fn text(&self) -> String {
+        format!("{} {}", self.title, self.class)
+    }
+
+    /// A host found in the title (some browser extensions put the URL
+    /// there), or otherwise the application, unless it is a browser.
+    pub fn hint(&self) -> Option<String> {
+        if let Some(host) = HOST.find(&self.title) {
+            return Some(host.as_str().to_lowercase());
+        }
+
+        let class = self.class.to_lowercase();
+        if class.is_empty() || BROWSERS.contains(&&class[..]) {
+            None
+        } else {
+            Some(class)
+        }
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(str::from_utf8(&output.stdout).ok()?.to_owned())
+}
+
+fn x11() -> Option<ActiveWindow> {
+    // _NET_ACTIVE_WINDOW(WINDOW): window id # 0x2a00007
+    let root = run("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+    let id = root.trim().rsplit(' ').next()?.to_owned();
+    if id == "0x0" {
+        return None;
+    }
+
+    let props = run(
+        "xprop",
+        &["-id", &id, "_NET_WM_NAME", "WM_NAME", "WM_CLASS"],
+    )?;
+    Some(xprop_window(&props))
+}
+
+/// The window from the properties xprop printed for it.
+fn xprop_window(props: &str) -> ActiveWindow {
+    let mut title = None;
+    let mut class = String::new();
+
+    for line in props.lines() {
+        let (name, value) = match line.split_once(" = ") {
+            Some(prop) => prop,
+            None => continue,
+        };
+        let values = quoted(value);
+        if name.starts_with("_NET_WM_NAME") || (name.starts_with("WM_NAME") && title.is_none()) {
+            title = values.into_iter().next();
+        } else if name.starts_with("WM_CLASS") {
+            // WM_CLASS(STRING) = "instance", "Class"
+            class = values.into_iter().last().unwrap_or_default();
+        }
+    }
+
+    ActiveWindow {
+        title: title.unwrap_or_default(),
+        class,
+    }
+}
+
+/// The quoted strings in an xprop value.
+fn quoted(value: &str) -> Vec<String> {
+    let mut strings = vec![];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            continue;
+        }
+        let mut s = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => s.extend(chars.next()),
+                '"' => break,
+                c => s.push(c),
+            }
+        }
+        strings.push(s);
+    }
+    strings
+}
+
+fn sway() -> Option<ActiveWindow> {
+    sway_window(&run("swaymsg", &["-t", "get_tree"])?)
+}
+
+/// The focused window in the tree from `swaymsg -t get_tree`.
+fn sway_window(tree: &str) -> Option<ActiveWindow> {
+    let tree: serde_json::Value = serde_json::from_str(tree).ok()?;
+    let node = focused_node(&tree)?;
+
+    // Wayland native windows have an app_id, XWayland ones a class.
+    let class = node["app_id"]
+        .as_str()
+        .or_else(|| node["window_properties"]["class"].as_str())
+        .unwrap_or_default();
+
+    Some(ActiveWindow {
+        title: node["name"].as_str().unwrap_or_default().to_owned(),
+        class: class.to_owned(),
+    })
+}
+
+fn focused_node(node: &serde_json::Value) -> Option<&serde_json::Value> {
+    if node["focused"].as_bool() == Some(true) {
+        return Some(node);
+    }
+    node["nodes"]
+        .as_array()
+        .into_iter()
+        .chain(node["floating_nodes"].as_array())
+        .flatten()
+        .find_map(focused_node)
+}
+
+fn hyprland() -> Option<ActiveWindow> {
+    hyprland_window(&run("hyprctl", &["activewindow", "-j"])?)
+}
+
+/// The window from `hyprctl activewindow -j`, which prints an empty object
+/// without one.
+fn hyprland_window(window: &str) -> Option<ActiveWindow> {
+    let window: serde_json::Value = serde_json::from_str(window).ok()?;
+
+    Some(ActiveWindow {
+        title: window["title"].as_str()?.to_owned(),
+        class: window["class"].as_str().unwrap_or_default().to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(title: &str, class: &str) -> ActiveWindow {
+        ActiveWindow {
+            title: title.to_owned(),
+            class: class.to_owned(),
+        }
+    }
+
+    #[test]
+    fn xprop_quoted() {
+        assert_eq!(
+            quoted(r#""navigator", "firefox""#),
+            vec!["navigator", "firefox"]
+        );
+        assert_eq!(quoted(r#""say \"hi\" \\ bye""#), vec![r#"say "hi" \ bye"#]);
+        assert_eq!(quoted("not found."), Vec::<String>::new());
+    }
+
+    #[test]
+    fn xprop_properties() {
+        let props = "_NET_WM_NAME(UTF8_STRING) = \"GitHub — Mozilla Firefox\"\n\
+                     WM_NAME(STRING) = \"GitHub - Mozilla Firefox\"\n\
+                     WM_CLASS(STRING) = \"Navigator\", \"firefox\"\n";
+        let active = xprop_window(props);
+        assert_eq!(active.title, "GitHub — Mozilla Firefox");
+        assert_eq!(active.class, "firefox");
+
+        // Without _NET_WM_NAME the title comes from WM_NAME.
+        let props = "_NET_WM_NAME:  not found.\n\
+                     WM_NAME(STRING) = \"xterm\"\n\
+                     WM_CLASS(STRING) = \"xterm\", \"XTerm\"\n";
+        let active = xprop_window(props);
+        assert_eq!(active.title, "xterm");
+        assert_eq!(active.class, "XTerm");
+    }
+
+    #[test]
+    fn sway_focused_node() {
+        // Trimmed from `swaymsg -t get_tree`.
+        let tree = r#"{
+            "id": 1, "type": "root", "name": "root", "focused": false,
+            "nodes": [{
+                "id": 3, "type": "output", "name": "eDP-1", "focused": false,
+                "nodes": [{
+                    "id": 4, "type": "workspace", "name": "1", "focused": false,
+                    "nodes": [{
+                        "id": 5, "type": "con", "name": "~", "focused": false,
+                        "app_id": "foot", "nodes": [], "floating_nodes": []
+                    }],
+                    "floating_nodes": [{
+                        "id": 6, "type": "floating_con", "name": "KeePassXC",
+                        "focused": true, "app_id": null,
+                        "window_properties": { "class": "KeePassXC", "instance": "keepassxc" },
+                        "nodes": [], "floating_nodes": []
+                    }]
+                }],
+                "floating_nodes": []
+            }],
+            "floating_nodes": []
+        }"#;
+        let active = sway_window(tree).unwrap();
+        assert_eq!(active.title, "KeePassXC");
+        assert_eq!(active.class, "KeePassXC");
+
+        let tree = tree.replace(r#""focused": true"#, r#""focused": false"#);
+        assert!(sway_window(&tree).is_none());
+    }
+
+    #[test]
+    fn hyprland_active_window() {
+        // Trimmed from `hyprctl activewindow -j`.
+        let output = r#"{
+            "address": "0x5f7c3a2e1b40", "mapped": true, "hidden": false,
+            "workspace": { "id": 2, "name": "2" }, "floating": false,
+            "class": "org.telegram.desktop", "title": "Telegram (3)",
+            "initialClass": "org.telegram.desktop", "initialTitle": "Telegram",
+            "pid": 4242, "xwayland": false
+        }"#;
+        let active = hyprland_window(output).unwrap();
+        assert_eq!(active.title, "Telegram (3)");
+        assert_eq!(active.class, "org.telegram.desktop");
+
+        assert!(hyprland_window("{}").is_none());
+        assert!(hyprland_window("Invalid").is_none());
+    }
+
+    #[test]
+    fn hint() {
+        let hint = |title, class| window(title, class).hint();
+        assert_eq!(
+            hint("Sign in - accounts.example.com:8443 - Firefox", "firefox"),
+            Some("accounts.example.com:8443".to_owned())
+        );
+        assert_eq!(hint("GitHub - Mozilla Firefox", "firefox"), None);
+        assert_eq!(hint("~", "foot"), Some("foot".to_owned()));
+        assert_eq!(
+            hint("Telegram", "org.telegram.desktop"),
+            Some("org.telegram.desktop".to_owned())
+        );
+        assert_eq!(hint("", ""), None);
+    }
+}